@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use advent_of_code::aoc_2019::intcode::fuzz;
+
+fuzz_target!(|data: &[u8]| {
+    fuzz::fuzz_bytes(data);
+});
@@ -1,83 +1,114 @@
+use super::intcode::symbolic::{Expr, SymbolicMachine};
+use super::intcode::{parse_code, Code, IntCode, VmError};
 
-type Code = i32;
-const INTCODE_HALT: Code = 99;
-const INTCODE_ADD: Code = 1;
-const INTCODE_MUL: Code = 2;
-
-pub fn main(puzzle_input: &String) {
+pub fn main(puzzle_input: &str) {
     let code = parse_code(puzzle_input);
-    println!("Part 1: {}", run_computer(&code, 12, 2));
+    println!("Part 1: {}", run_computer(&code, 12, 2).unwrap());
     let (noun, verb) = gravity_assist(&code, 19690720);
     println!("Part 2: {}", noun * 100 + verb);
 }
 
-fn run_computer(code: &Vec<Code>, noun: Code, verb: Code) -> Code {
+fn run_computer(code: &[Code], noun: Code, verb: Code) -> Result<Code, VmError> {
     let mut computer = IntCode::new(code);
-    computer.code[1] = noun;
-    computer.code[2] = verb;
-    computer.run();
-    computer.code[0]
+    computer.memory[1] = noun;
+    computer.memory[2] = verb;
+    computer.run()?;
+    Ok(computer.memory[0])
+}
+
+// Falls back to running the program when its output isn't linear or the
+// solution can't be computed
+fn gravity_assist(code: &[Code], target: Code) -> (Code, Code) {
+    symbolic_output(code)
+        .and_then(|expr| expr.as_linear(2))
+        .and_then(|(constant, coefficients)| solve_linear(constant, coefficients[0], coefficients[1], target))
+        .unwrap_or_else(|| brute_force(code, target))
+}
+
+// Expression of the program's output in terms of the noun and verb
+fn symbolic_output(code: &[Code]) -> Option<Expr> {
+    let mut machine = SymbolicMachine::new(code);
+    machine.bind(1, "noun");
+    machine.bind(2, "verb");
+    machine.run().ok()?;
+    machine.load(0)
+}
+
+// First noun and verb, in the brute force order, such that
+// constant + a * noun + b * verb == target. None if there are none, or if
+// the arithmetic overflows.
+fn solve_linear(constant: Code, a: Code, b: Code, target: Code) -> Option<(Code, Code)> {
+    for noun in 0..100 {
+        let rest = target.checked_sub(constant)?.checked_sub(a.checked_mul(noun)?)?;
+        let verb = match b {
+            0 if rest == 0 => 0,
+            0 => continue,
+            _ if rest.checked_rem(b)? != 0 => continue,
+            _ => rest.checked_div(b)?,
+        };
+        if (0..100).contains(&verb) {
+            return Some((noun, verb));
+        }
+    }
+    None
 }
 
-fn gravity_assist(code: &Vec<Code>, target: Code) -> (Code, Code) {
+fn brute_force(code: &[Code], target: Code) -> (Code, Code) {
     for noun in 0..100 {
         for verb in 0..100 {
-            if run_computer(code, noun, verb) == target {
+            if run_computer(code, noun, verb) == Ok(target) {
                 return (noun, verb)
             }
         }
     }
-    return (-1, -1)
+    (-1, -1)
 }
 
-struct IntCode {
-    pub code: Vec<Code>,
-    pointer: usize,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn parse_code(contents: &String) -> Vec<Code> {
-    let mut code = Vec::new();
-    for line in contents.split(",") {
-        if let Ok(n) = line.trim().parse() {
-            code.push(n);
-        }
-    }
-    code
-}
+    const INPUT: &str = include_str!("../../data/2019/day_02.txt");
 
-impl IntCode {
-    fn new(code: &Vec<Code>) -> IntCode {
-        let code = code.clone();
-        IntCode { code, pointer: 0 }
-    }
+    #[test]
+    fn test_symbolic_output_is_linear() {
+        let code = parse_code(INPUT);
+        let (constant, coefficients) = symbolic_output(&code).unwrap().as_linear(2).unwrap();
 
-    fn is_over(&self) -> bool {
-        self.code[self.pointer] == INTCODE_HALT
+        for &(noun, verb) in &[(12, 2), (0, 0), (99, 99), (42, 7)] {
+            let expected = run_computer(&code, noun, verb).unwrap();
+            assert_eq!(expected, constant + coefficients[0] * noun + coefficients[1] * verb);
+        }
     }
 
-    fn run_add(&mut self) {
-        let a = self.code[self.pointer + 1] as usize;
-        let b = self.code[self.pointer + 2] as usize;
-        let c = self.code[self.pointer + 3] as usize;
-        self.code[c] = self.code[a] + self.code[b];
-        self.pointer += 4;
+    #[test]
+    fn test_gravity_assist_matches_brute_force() {
+        let code = parse_code(INPUT);
+        for &target in &[19690720, 5866663, 1] {
+            assert_eq!(brute_force(&code, target), gravity_assist(&code, target));
+        }
     }
 
-    fn run_mul(&mut self) {
-        let a = self.code[self.pointer + 1] as usize;
-        let b = self.code[self.pointer + 2] as usize;
-        let c = self.code[self.pointer + 3] as usize;
-        self.code[c] = self.code[a] * self.code[b];
-        self.pointer += 4;
+    #[test]
+    fn test_non_linear_fallback() {
+        // mem[0] = noun * verb
+        let code = vec![1102, 0, 0, 0, 99];
+        assert_eq!(None, symbolic_output(&code).unwrap().as_linear(2));
+        assert_eq!((1, 42), gravity_assist(&code, 42));
     }
 
-    fn run(&mut self) {
-        while ! self.is_over() {
-            match self.code[self.pointer] {
-                INTCODE_ADD => self.run_add(),
-                INTCODE_MUL => self.run_mul(),
-                _ => panic!("Unknown intcode instruction"),
-            }
-        }
+    #[test]
+    fn test_oversized_coefficients() {
+        assert_eq!(None, solve_linear(0, Code::MAX, 1, 500));
+        assert_eq!(None, solve_linear(Code::MIN, 1, 1, 1));
+        assert_eq!(Some((0, 5)), solve_linear(0, Code::MAX, 1, 5));
+        // MIN / -1 at noun 1
+        assert_eq!(None, solve_linear(100, Code::MAX - 99, -1, 0));
+
+        // mem[0] = noun * MAX + verb, which overflows for nouns above 1
+        let code = vec![1, 0, 0, 3, 1002, 1, Code::MAX, 0, 1, 0, 2, 0, 99];
+        assert_eq!(Some((0, vec![Code::MAX, 1])), symbolic_output(&code).unwrap().as_linear(2));
+        assert_eq!((-1, -1), gravity_assist(&code, 500));
+        assert_eq!((0, 42), gravity_assist(&code, 42));
     }
 }
@@ -1,115 +1,624 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::fs;
 
-pub fn main(puzzle_input: &String) {
-    let (mut fst, mut snd) = Path::parse_puzzle_input(puzzle_input).unwrap();
+use crate::geometry::{Direction8, Point};
+use crate::PuzzleError;
 
-    let posmap_fst = fst.collect_positions();
-    let posmap_snd = snd.collect_positions();
-    let inter = intersections(&posmap_fst, &posmap_snd);
-   
-    println!("Part 1: {}", closest_distance(&inter).unwrap());
-    println!("Part 2: {}", shortest_path(&inter).unwrap());
+pub fn main(puzzle_input: &str, options: &[String]) {
+    let options = match Options::parse(options) {
+        Ok(options) => options,
+        Err(err) => return println!("Invalid options: {}", err),
+    };
+    let paths = match Path::parse_puzzle_input(puzzle_input) {
+        Ok(paths) => paths,
+        Err(err) => return println!("Invalid wires: {}", err),
+    };
+    // Loops are a property of each wire, so a single one is enough
+    if options.loops {
+        for (index, path) in paths.iter().enumerate() {
+            println!("{}", describe_loops(index + 1, &path.clone().loops()));
+        }
+    }
+    if paths.len() < 2 {
+        if !options.loops {
+            println!("Need at least two wires, found {}", paths.len());
+        }
+        return;
+    }
+    let corners: Vec<Vec<Position>> = paths.iter().map(Path::corners).collect();
+    let wires = Wires::new(paths, options.algorithm, options.visit);
 
+    let common = wires.common();
+    if let Some(file) = &options.render {
+        match render(file, &corners, &common) {
+            Ok(()) => println!("Rendered wires to {}", file),
+            Err(err) => println!("Couldn't render wires: {}", err),
+        }
+    }
+    if options.sort.is_some() || options.top.is_some() {
+        let metric = options.sort.unwrap_or(Metric::Distance);
+        print_report(&wires.report(metric, options.top), wires.count);
+    }
+    if wires.count == 2 {
+        println!("Part 1: {}", closest_distance(&common).unwrap());
+        println!("Part 2: {}", shortest_path(&common).unwrap());
+        return;
+    }
+    report("All wires", &common);
+    report("Any two wires", &wires.pairwise());
+    for first in 0..wires.count {
+        for second in first + 1..wires.count {
+            report(&format!("Wires {} and {}", first + 1, second + 1), &wires.pair(first, second));
+        }
+    }
+}
+
+fn report(name: &str, intersections: &PosMap) {
+    let closest = closest_distance(intersections).map_or("none".to_string(), |d| d.to_string());
+    let shortest = shortest_path(intersections).map_or("none".to_string(), |s| s.to_string());
+    println!("{}: closest {}, shortest {}", name, closest, shortest);
+}
+
+fn print_report(intersections: &[Intersection], count: usize) {
+    let wires: Vec<String> = (1..=count).map(|wire| format!("{:>10}", format!("wire {}", wire))).collect();
+    println!("{:>11} {:>11} {:>10} {:>10} {}", "x", "y", "distance", "steps", wires.join(" "));
+    for intersection in intersections {
+        let steps: Vec<String> = intersection.steps.iter().map(|steps| format!("{:>10}", steps)).collect();
+        println!("{:>11} {:>11} {:>10} {:>10} {}", intersection.position.x, intersection.position.y,
+                 intersection.distance, intersection.combined_steps(), steps.join(" "));
+    }
 }
 
-type Position = (i16, i16);
-type PosMap = HashMap<Position, u16>;
+fn describe_loops(wire: usize, loops: &Loops) -> String {
+    let mut text = format!(
+        "Wire {}: {} cells visited more than once, {} loops, loop-free path of {} steps instead of {}",
+        wire, loops.revisited.len(), loops.loops.len(), loops.loop_free.len(), loops.steps);
+    if let Some(longest) = loops.loops.iter().max_by_key(|l| (l.length(), std::cmp::Reverse(l.start))) {
+        text += &format!("\n    longest loop at {}, from step {} to step {}", longest.position, longest.start, longest.end);
+    }
+    text
+}
 
+// Day options: --algorithm cells|segments, --render <file.svg|file.ppm>,
+// --sort distance|steps and --top <k> to list the intersections,
+// --visit first|latest for the steps to cells a wire passes several times,
+// and --loops to analyse how each wire crosses itself
 #[derive(Debug, PartialEq)]
-enum Direction { Up, Right, Down, Left }
+struct Options {
+    algorithm: Algorithm,
+    render: Option<String>,
+    sort: Option<Metric>,
+    top: Option<usize>,
+    visit: Visit,
+    loops: bool,
+}
 
-impl Direction {
-    fn step(&self, position: Position) -> Position {
-        let (x, y) = position;
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, PuzzleError> {
+        let mut options = Options {
+            algorithm: Algorithm::Segments,
+            render: None,
+            sort: None,
+            top: None,
+            visit: Visit::First,
+            loops: false,
+        };
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || match args.next() {
+                Some(value) => Ok(value.as_str()),
+                None => Err(PuzzleError::new(&format!("missing value for {}", arg))),
+            };
+            match arg.as_str() {
+                "--algorithm" => options.algorithm = match value()? {
+                    "cells" => Algorithm::Cells,
+                    "segments" => Algorithm::Segments,
+                    other => return Err(PuzzleError::new(&format!("unknown algorithm '{}'", other))),
+                },
+                "--render" => options.render = Some(value()?.to_string()),
+                "--sort" => options.sort = Some(match value()? {
+                    "distance" => Metric::Distance,
+                    "steps" => Metric::Steps,
+                    other => return Err(PuzzleError::new(&format!("unknown metric '{}'", other))),
+                }),
+                "--top" => options.top = Some(value()?.parse()?),
+                "--visit" => options.visit = match value()? {
+                    "first" => Visit::First,
+                    "latest" => Visit::Latest,
+                    other => return Err(PuzzleError::new(&format!("unknown visit '{}'", other))),
+                },
+                "--loops" => options.loops = true,
+                other => return Err(PuzzleError::new(&format!("unknown option {}", other))),
+            }
+        }
+        Ok(options)
+    }
+}
+
+type Position = Point<i32>;
+// Steps a single wire takes to reach each cell
+type Visits = HashMap<Position, u32>;
+// Combined steps of several wires reaching each cell
+type PosMap = HashMap<Position, u64>;
+
+// How to find the cells where the wires cross
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Algorithm {
+    // Visit every cell of both wires
+    Cells,
+    // Compare the straight runs of the wires, sorted by line
+    Segments,
+}
+
+// Which steps count when a wire passes a cell several times
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Visit {
+    First,
+    Latest,
+}
+
+impl Visit {
+    fn keep(self, steps: u32, other: u32) -> u32 {
         match self {
-            Direction::Up => (x, y + 1),
-            Direction::Down => (x, y - 1),
-            Direction::Right => (x + 1, y),
-            Direction::Left => (x - 1, y),
+            Visit::First => steps.min(other),
+            Visit::Latest => steps.max(other),
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+// A wire coming back to a cell, from its previous visit to this one
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Loop {
+    position: Position,
+    start: u32,
+    end: u32,
+}
+
+impl Loop {
+    fn length(&self) -> u32 {
+        self.end - self.start
+    }
+}
+
+// How a wire crosses itself. The origin counts as visited at step 0.
+#[derive(Debug, Clone, PartialEq)]
+struct Loops {
+    // Cells visited more than once, with the steps of every visit
+    revisited: BTreeMap<Position, Vec<u32>>,
+    // In the order they close
+    loops: Vec<Loop>,
+    // Cells of the path with every loop cut out, in order
+    loop_free: Vec<Position>,
+    steps: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 struct Move {
-    direction: Direction,
-    steps: u16,
+    direction: Direction8,
+    steps: u32,
 }
 
 impl Move {
-    fn parse(string: &str) -> Option<Move> {
-        let mut chars = string.chars();
-
-        let direction = match chars.next() {
-            Some('U') => Direction::Up,
-            Some('R') => Direction::Right,
-            Some('D') => Direction::Down,
-            Some('L') => Direction::Left,
-            _         => return None,
+    // A direction, possibly diagonal like UR or LD, then a length that
+    // may be zero
+    fn parse(string: &str) -> Result<Move, PuzzleError> {
+        if string.is_empty() {
+            return Err(PuzzleError::new("empty move"));
+        }
+        let split = string.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(string.len());
+        let (direction, steps) = string.split_at(split);
+
+        let direction = match direction {
+            "U" => Direction8::Up,
+            "R" => Direction8::Right,
+            "D" => Direction8::Down,
+            "L" => Direction8::Left,
+            "UR" | "RU" => Direction8::UpRight,
+            "DR" | "RD" => Direction8::DownRight,
+            "DL" | "LD" => Direction8::DownLeft,
+            "UL" | "LU" => Direction8::UpLeft,
+            _ => return Err(PuzzleError::new(&format!("unknown direction '{}'", direction))),
         };
 
-        let steps: String = chars.collect();
-        let steps: u16 = match steps.parse() {
+        let steps: u32 = match steps.parse() {
             Ok(value) => value,
-            Err(_) => return None,
+            Err(_) => return Err(PuzzleError::new(&format!("invalid length '{}'", steps))),
         };
 
-        Some(Move { direction, steps })
+        Ok(Move { direction, steps })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Path {
     position: Position,
     moves: VecDeque<Move>,
-    current_steps: u16,
+    current_steps: u32,
 }
 
 impl Path {
-    fn parse(string: &str) -> Path {
-        let mut moves = VecDeque::new();
-        for mv_str in string.split(",") {
-            if let Some(mv) = Move::parse(mv_str) {
-                moves.push_back(mv);
-            }
+    // Moves are separated by commas, with optional whitespace around them.
+    // Also fails if a cell of the path or its step count doesn't fit in the
+    // types used for them, so that walking the path can't overflow.
+    fn parse(string: &str) -> Result<Path, PuzzleError> {
+        let mut path = Path { position: Position::origin(), moves: VecDeque::new(), current_steps: 0 };
+        let (mut end, mut length) = (path.position, 0u32);
+
+        for (index, mv_str) in string.split(',').map(str::trim).enumerate() {
+            let error = |reason: &str| PuzzleError::new(&format!("move {} '{}' {}", index + 1, mv_str, reason));
+            let mv = Move::parse(mv_str).map_err(|err| error(&format!("can't be parsed: {}", err)))?;
+            end = i32::try_from(mv.steps).ok()
+                .and_then(|steps| end.checked_step(mv.direction, steps))
+                .ok_or_else(|| error("leaves the representable range"))?;
+            length = length.checked_add(mv.steps)
+                .ok_or_else(|| error(&format!("makes the wire longer than {} steps", u32::MAX)))?;
+            path.moves.push_back(mv);
         }
-        Path { position: (0, 0), moves, current_steps: 0 }
+        Ok(path)
     }
 
-    fn parse_puzzle_input(string: &str) -> Option<(Path, Path)> {
-        let mut lines = string.lines();
-        
-        let first = match lines.next() {
-            Some(line) => Path::parse(&line),
-            None => return None,
-        };
-        let second = match lines.next() {
-            Some(line) => Path::parse(&line),
-            None => return None,
-        };
-        Some((first, second))
+    // One wire per non-empty line
+    fn parse_puzzle_input(string: &str) -> Result<Vec<Path>, PuzzleError> {
+        string.lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(index, line)| {
+                Path::parse(line).map_err(|err| PuzzleError::new(&format!("wire {}: {}", index + 1, err)))
+            })
+            .collect()
     }
 
-    fn collect_positions(&mut self) -> PosMap {
+    fn collect_positions(&mut self, visit: Visit) -> Visits {
         let mut pos_counts = HashMap::new();
-        
-        for (i, pos) in self.enumerate() {
-            pos_counts.entry(pos).or_insert((i + 1) as u16 );
+
+        for (steps, pos) in (1..).zip(self) {
+            let entry = pos_counts.entry(pos).or_insert(steps);
+            *entry = visit.keep(*entry, steps);
         };
 
         pos_counts
     }
+
+    // Walks the path, cutting each loop out of the loop-free path as soon
+    // as it closes
+    fn loops(&mut self) -> Loops {
+        let origin = self.position;
+        let mut visits: HashMap<Position, Vec<u32>> = HashMap::new();
+        visits.insert(origin, vec![0]);
+        let mut loops = Vec::new();
+        let mut loop_free = vec![origin];
+        let mut index: HashMap<Position, usize> = HashMap::new();
+        index.insert(origin, 0);
+        let mut total = 0;
+
+        for (steps, position) in (1..).zip(self) {
+            total = steps;
+            let previous = visits.entry(position).or_default();
+            if let Some(&start) = previous.last() {
+                loops.push(Loop { position, start, end: steps });
+            }
+            previous.push(steps);
+
+            match index.get(&position) {
+                Some(&at) => {
+                    for cell in loop_free.drain(at + 1..) {
+                        index.remove(&cell);
+                    }
+                }
+                None => {
+                    index.insert(position, loop_free.len());
+                    loop_free.push(position);
+                }
+            }
+        }
+
+        loop_free.remove(0);
+        let revisited = visits.into_iter().filter(|(_, steps)| steps.len() > 1).collect();
+        Loops { revisited, loops, loop_free, steps: total }
+    }
+
+    // Straight runs of a path that hasn't been iterated yet
+    fn segments(&self) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        let mut start = self.position;
+        let mut steps = 0;
+        for mv in self.moves.iter().filter(|mv| mv.steps > 0) {
+            let segment = Segment { start, direction: mv.direction, length: mv.steps, steps };
+            start = segment.end();
+            steps += mv.steps;
+            segments.push(segment);
+        }
+        segments
+    }
+
+    // Where the path starts and turns, then where it ends
+    fn corners(&self) -> Vec<Position> {
+        let mut corners = vec![self.position];
+        corners.extend(self.segments().iter().map(Segment::end));
+        corners
+    }
+}
+
+// The `length` cells following `start`, reached after `steps` steps
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Segment {
+    start: Position,
+    direction: Direction8,
+    length: u32,
+    steps: u32,
+}
+
+impl Segment {
+    fn end(&self) -> Position {
+        self.start + self.direction.offset() * self.length as i32
+    }
+
+    fn axis(&self) -> Axis {
+        match self.direction {
+            Direction8::Left | Direction8::Right => Axis::Horizontal,
+            Direction8::Up | Direction8::Down => Axis::Vertical,
+            Direction8::UpRight | Direction8::DownLeft => Axis::Rising,
+            Direction8::DownRight | Direction8::UpLeft => Axis::Falling,
+        }
+    }
+
+    // Lowest and highest levels on the axis of the cells along the segment:
+    // a single level for the segment's own axis
+    fn levels(&self, axis: Axis) -> (i64, i64) {
+        let first = axis.level(self.start + self.direction.offset());
+        let last = axis.level(self.end());
+        (first.min(last), first.max(last))
+    }
+
+    fn steps_to(&self, position: Position) -> u32 {
+        self.steps + position.cast::<i64>().chebyshev(self.start.cast()) as u32
+    }
+
+    // Cells of both segments, for segments in any of the eight directions
+    fn shared_cells(&self, other: &Segment) -> Vec<Position> {
+        let (d1, d2) = (self.direction.offset::<i64>(), other.direction.offset::<i64>());
+        let r = other.start.cast::<i64>() - self.start.cast();
+        let (len1, len2) = (i64::from(self.length), i64::from(other.length));
+        let cell = |k: i64| self.start + self.direction.offset() * k as i32;
+        let cross = |a: Point<i64>, b: Point<i64>| a.x * b.y - a.y * b.x;
+        let dot = |a: Point<i64>, b: Point<i64>| a.x * b.x + a.y * b.y;
+
+        if cross(d1, d2) == 0 {
+            if cross(r, d1) != 0 {
+                return Vec::new();
+            }
+            // On the same line: the other segment's cells are at steps
+            // start + sign * j along this one, for j in 1..=len2
+            let start = dot(r, d1) / dot(d1, d1);
+            let sign = dot(d1, d2) / dot(d1, d1);
+            let (low, high) = match sign > 0 {
+                true => (start + 1, start + len2),
+                false => (start - len2, start - 1),
+            };
+            return (low.max(1)..=high.min(len1)).map(cell).collect();
+        }
+
+        // start + d1 * k == other.start + d2 * j, with whole steps k and j
+        let det = cross(d1, d2);
+        let (k, j) = (cross(r, d2), cross(r, d1));
+        if k % det != 0 || j % det != 0 {
+            return Vec::new();
+        }
+        let (k, j) = (k / det, j / det);
+        if (1..=len1).contains(&k) && (1..=len2).contains(&j) {
+            vec![cell(k)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+// The four directions of lines a segment can lie on
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Axis {
+    Horizontal,
+    Vertical,
+    // Up and to the right
+    Rising,
+    Falling,
+}
+
+impl Axis {
+    const ALL: [Axis; 4] = [Axis::Horizontal, Axis::Vertical, Axis::Rising, Axis::Falling];
+
+    // Tells apart the parallel lines of the axis: the same for every cell
+    // of a line
+    fn level(self, position: Position) -> i64 {
+        let Point { x, y } = position.cast::<i64>();
+        match self {
+            Axis::Horizontal => y,
+            Axis::Vertical => x,
+            Axis::Rising => x - y,
+            Axis::Falling => x + y,
+        }
+    }
+}
+
+// Segments on each line, sorted by the line's level
+type Lines = BTreeMap<i64, Vec<Segment>>;
+
+// Segments by axis, so that each segment is only compared with the ones
+// on lines it crosses or lies on
+struct SegmentIndex {
+    lines: [Lines; 4],
+}
+
+impl SegmentIndex {
+    fn new(segments: &[Segment]) -> SegmentIndex {
+        let mut index = SegmentIndex { lines: Default::default() };
+        for segment in segments {
+            let axis = segment.axis();
+            let (level, _) = segment.levels(axis);
+            index.lines[axis as usize].entry(level).or_default().push(*segment);
+        }
+        index
+    }
+
+    // Segments that may share cells with the given one
+    fn candidates<'a>(&'a self, segment: &'a Segment) -> impl Iterator<Item = &'a Segment> + 'a {
+        Axis::ALL.iter().flat_map(move |&axis| {
+            let (low, high) = segment.levels(axis);
+            self.lines[axis as usize].range(low..=high).flat_map(|(_, others)| others)
+        })
+    }
+}
+
+// Cells where two wires cross, with the steps each wire takes to reach
+// them: the same counts as `collect_positions`
+fn segment_intersections(first: &[Segment], second: &[Segment], visit: Visit) -> HashMap<Position, (u32, u32)> {
+    let index = SegmentIndex::new(second);
+    let mut steps: HashMap<Position, (u32, u32)> = HashMap::new();
+    let mut record = |position: Position, fst: &Segment, snd: &Segment| {
+        let (steps_fst, steps_snd) = (fst.steps_to(position), snd.steps_to(position));
+        let entry = steps.entry(position).or_insert((steps_fst, steps_snd));
+        *entry = (visit.keep(entry.0, steps_fst), visit.keep(entry.1, steps_snd));
+    };
+
+    for segment in first {
+        for other in index.candidates(segment) {
+            for position in segment.shared_cells(other) {
+                record(position, segment, other);
+            }
+        }
+    }
+    steps
+}
+
+// Steps each wire crossing a cell takes to reach it, by wire index
+type Steps = BTreeMap<usize, u32>;
+
+// What makes an intersection better than another
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Metric {
+    // Manhattan distance to the origin
+    Distance,
+    // Steps of all wires combined
+    Steps,
+}
+
+// A cell crossed by every wire
+#[derive(Debug, Clone, PartialEq)]
+struct Intersection {
+    position: Position,
+    distance: u64,
+    // Steps each wire takes to reach the cell, in wire order
+    steps: Vec<u32>,
+}
+
+impl Intersection {
+    fn combined_steps(&self) -> u64 {
+        self.steps.iter().map(|&steps| u64::from(steps)).sum()
+    }
+
+    // Ties are broken by the other metric, then by position
+    fn sort_key(&self, metric: Metric) -> (u64, u64, Position) {
+        match metric {
+            Metric::Distance => (self.distance, self.combined_steps(), self.position),
+            Metric::Steps => (self.combined_steps(), self.distance, self.position),
+        }
+    }
+}
+
+// The cells crossed by at least two wires
+struct Wires {
+    count: usize,
+    crossings: HashMap<Position, Steps>,
+}
+
+impl Wires {
+    fn new(mut paths: Vec<Path>, algorithm: Algorithm, visit: Visit) -> Wires {
+        let mut crossings: HashMap<Position, Steps> = HashMap::new();
+
+        match algorithm {
+            Algorithm::Cells => {
+                for (wire, path) in paths.iter_mut().enumerate() {
+                    for (position, steps) in path.collect_positions(visit) {
+                        crossings.entry(position).or_default().insert(wire, steps);
+                    }
+                }
+                crossings.retain(|_, steps| steps.len() > 1);
+            }
+            Algorithm::Segments => {
+                let segments: Vec<Vec<Segment>> = paths.iter().map(Path::segments).collect();
+                for first in 0..segments.len() {
+                    for second in first + 1..segments.len() {
+                        let pair = segment_intersections(&segments[first], &segments[second], visit);
+                        for (position, (steps_fst, steps_snd)) in pair {
+                            let steps = crossings.entry(position).or_default();
+                            steps.insert(first, steps_fst);
+                            steps.insert(second, steps_snd);
+                        }
+                    }
+                }
+            }
+        }
+        Wires { count: paths.len(), crossings }
+    }
+
+    // Cells crossed by every wire, with the steps of all wires combined
+    fn common(&self) -> PosMap {
+        self.crossings.iter()
+            .filter(|(_, steps)| steps.len() == self.count)
+            .map(|(&position, steps)| (position, steps.values().map(|&s| u64::from(s)).sum()))
+            .collect()
+    }
+
+    // Cells crossed by every wire, best first, keeping at most `limit`
+    fn report(&self, metric: Metric, limit: Option<usize>) -> Vec<Intersection> {
+        let mut intersections: Vec<Intersection> = self.crossings.iter()
+            .filter(|(_, steps)| steps.len() == self.count)
+            .map(|(&position, steps)| Intersection {
+                position,
+                distance: distance(&position),
+                steps: steps.values().copied().collect(),
+            })
+            .collect();
+        intersections.sort_by_key(|intersection| intersection.sort_key(metric));
+        intersections.truncate(limit.unwrap_or(intersections.len()));
+        intersections
+    }
+
+    // Cells crossed by the two given wires, with their steps combined
+    fn pair(&self, first: usize, second: usize) -> PosMap {
+        self.crossings.iter()
+            .filter_map(|(&position, steps)| {
+                Some((position, u64::from(*steps.get(&first)?) + u64::from(*steps.get(&second)?)))
+            })
+            .collect()
+    }
+
+    // Cells crossed by any two wires, with the steps of the two wires
+    // reaching them first combined
+    fn pairwise(&self) -> PosMap {
+        self.crossings.iter()
+            .map(|(&position, steps)| {
+                let mut steps: Vec<u64> = steps.values().map(|&s| u64::from(s)).collect();
+                steps.sort_unstable();
+                (position, steps[0] + steps[1])
+            })
+            .collect()
+    }
 }
 
 impl Iterator for Path {
     type Item = Position;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.moves.len() == 0 { return None; };
+        while self.moves.front()?.steps == 0 {
+            self.moves.pop_front();
+        }
 
         let cur_move = &self.moves[0];
-        self.position = cur_move.direction.step(self.position);
+        self.position = self.position.step(cur_move.direction);
 
         self.current_steps += 1;
         if self.current_steps == cur_move.steps {
@@ -121,26 +630,187 @@ impl Iterator for Path {
     }
 }
 
-fn intersections(first: &PosMap, second: &PosMap) -> PosMap {
-    let mut intersections: PosMap = HashMap::new();
-    for (pos, steps_fst) in first.iter() {
-        if let Some(steps_snd) = second.get(pos) {
-            intersections.insert(*pos, steps_fst + steps_snd);
-        }
-    };
-    intersections
+// Computed with wider coordinates, which can't overflow
+fn distance(position: &Position) -> u64 {
+    position.cast::<i64>().manhattan(Point::origin()) as u64
 }
 
-fn closest_distance(intersections: &PosMap) -> Option<u16> {
-    intersections.keys()
-        .map(|pos| {pos.0.abs() as u16 + pos.1.abs() as u16})
-        .min()
+fn closest_distance(intersections: &PosMap) -> Option<u64> {
+    intersections.keys().map(distance).min()
 }
 
-fn shortest_path(intersections: &PosMap) -> Option<u16> {
+fn shortest_path(intersections: &PosMap) -> Option<u64> {
     intersections.values().min().copied()
 }
 
+// Rendering
+
+type Colour = (u8, u8, u8);
+
+// Cycled through when there are more wires
+const WIRE_COLOURS: [Colour; 6] = [
+    (31, 119, 180), (255, 127, 14), (44, 160, 44), (148, 103, 189), (23, 190, 207), (140, 86, 75),
+];
+const ORIGIN_COLOUR: Colour = (0, 0, 0);
+const CROSSING_COLOUR: Colour = (128, 128, 128);
+const CLOSEST_COLOUR: Colour = (214, 39, 40);
+const SHORTEST_COLOUR: Colour = (227, 119, 194);
+
+// Longest side of raster images in pixels, and the blank border around them
+const IMAGE_SIZE: usize = 1000;
+const IMAGE_MARGIN: usize = 10;
+
+// Point to draw on top of the wires, with its colour and relative size
+type Mark = (Point<i64>, Colour, u8);
+
+// Origin, intersections, and the closest and shortest ones drawn last so
+// that they stay visible
+fn marks(intersections: &PosMap) -> Vec<Mark> {
+    let mut crossings: Vec<(&Position, &u64)> = intersections.iter().collect();
+    crossings.sort();
+
+    let mut marks: Vec<Mark> = crossings.iter().map(|(p, _)| (p.cast(), CROSSING_COLOUR, 1)).collect();
+    let closest = closest_distance(intersections);
+    let shortest = shortest_path(intersections);
+    for &(position, &steps) in crossings.iter() {
+        if Some(distance(position)) == closest {
+            marks.push((position.cast(), CLOSEST_COLOUR, 2));
+        }
+        if Some(steps) == shortest {
+            marks.push((position.cast(), SHORTEST_COLOUR, 2));
+        }
+    }
+    marks.push((Point::origin(), ORIGIN_COLOUR, 2));
+    marks
+}
+
+// Lowest and highest corners of the box around the wires and the origin
+fn bounds(wires: &[Vec<Point<i64>>]) -> (Point<i64>, Point<i64>) {
+    let (mut low, mut high): (Point<i64>, Point<i64>) = (Point::origin(), Point::origin());
+    for corner in wires.iter().flatten() {
+        low = Point::new(low.x.min(corner.x), low.y.min(corner.y));
+        high = Point::new(high.x.max(corner.x), high.y.max(corner.y));
+    }
+    (low, high)
+}
+
+fn hex((r, g, b): Colour) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+// SVG's y axis points down: points are drawn at (x, -y)
+fn render_svg(wires: &[Vec<Point<i64>>], marks: &[Mark]) -> String {
+    let (low, high) = bounds(wires);
+    let extent = (high.x - low.x).max(high.y - low.y).max(1);
+    let margin = extent / 50 + 1;
+    let radius = extent as f64 / 200.0;
+    let (x, y) = (low.x - margin, -high.y - margin);
+    let (width, height) = (high.x - low.x + 2 * margin, high.y - low.y + 2 * margin);
+
+    let mut svg = String::new();
+    writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" viewBox="{} {} {} {}">"#,
+             IMAGE_SIZE, x, y, width, height).unwrap();
+    writeln!(svg, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="white"/>"#, x, y, width, height).unwrap();
+    for (index, corners) in wires.iter().enumerate() {
+        let points: Vec<String> = corners.iter().map(|p| format!("{},{}", p.x, -p.y)).collect();
+        writeln!(svg, r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2" vector-effect="non-scaling-stroke"/>"#,
+                 points.join(" "), hex(WIRE_COLOURS[index % WIRE_COLOURS.len()])).unwrap();
+    }
+    for &(position, colour, size) in marks {
+        writeln!(svg, r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+                 position.x, -position.y, radius * size as f64, hex(colour)).unwrap();
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<Colour>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Canvas {
+        Canvas { width, height, pixels: vec![(255, 255, 255); width * height] }
+    }
+
+    fn set(&mut self, (x, y): (usize, usize), colour: Colour) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = colour;
+        }
+    }
+
+    fn line(&mut self, from: (usize, usize), to: (usize, usize), colour: Colour) {
+        let (dx, dy) = (to.0 as f64 - from.0 as f64, to.1 as f64 - from.1 as f64);
+        let steps = dx.abs().max(dy.abs()) as usize;
+        for step in 0..=steps {
+            let t = if steps == 0 { 0.0 } else { step as f64 / steps as f64 };
+            let x = (from.0 as f64 + dx * t).round() as usize;
+            let y = (from.1 as f64 + dy * t).round() as usize;
+            self.set((x, y), colour);
+        }
+    }
+
+    fn square(&mut self, (x, y): (usize, usize), radius: usize, colour: Colour) {
+        for py in y.saturating_sub(radius)..=y + radius {
+            for px in x.saturating_sub(radius)..=x + radius {
+                self.set((px, py), colour);
+            }
+        }
+    }
+
+    // Binary PPM: a short header followed by the RGB bytes, row by row
+    fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for &(r, g, b) in &self.pixels {
+            ppm.extend_from_slice(&[r, g, b]);
+        }
+        ppm
+    }
+}
+
+// Scales the wires down so that the longest side fits in `IMAGE_SIZE`
+fn render_ppm(wires: &[Vec<Point<i64>>], marks: &[Mark]) -> Vec<u8> {
+    let (low, high) = bounds(wires);
+    let extent = (high.x - low.x).max(high.y - low.y).max(1) as f64;
+    let scale = |value: i64, low: i64| {
+        ((value - low) as f64 * (IMAGE_SIZE - 1) as f64 / extent).round() as usize + IMAGE_MARGIN
+    };
+    let width = scale(high.x, low.x) + IMAGE_MARGIN + 1;
+    let height = scale(high.y, low.y) + IMAGE_MARGIN + 1;
+    let pixel = |point: Point<i64>| (scale(point.x, low.x), height - 1 - scale(point.y, low.y));
+
+    let mut canvas = Canvas::new(width, height);
+    for (index, corners) in wires.iter().enumerate() {
+        let colour = WIRE_COLOURS[index % WIRE_COLOURS.len()];
+        for pair in corners.windows(2) {
+            canvas.line(pixel(pair[0]), pixel(pair[1]), colour);
+        }
+    }
+    for &(position, colour, size) in marks {
+        canvas.square(pixel(position), 2 * size as usize, colour);
+    }
+    canvas.to_ppm()
+}
+
+// Writes an SVG or PPM image of the wires, depending on the file extension
+fn render(file: &str, corners: &[Vec<Position>], intersections: &PosMap) -> Result<(), PuzzleError> {
+    let wires: Vec<Vec<Point<i64>>> = corners.iter()
+        .map(|corners| corners.iter().map(|p| p.cast()).collect())
+        .collect();
+    let marks = marks(intersections);
+    let image = if file.ends_with(".svg") {
+        render_svg(&wires, &marks).into_bytes()
+    } else if file.ends_with(".ppm") {
+        render_ppm(&wires, &marks)
+    } else {
+        return Err(PuzzleError::new(&format!("can't tell the image format of '{}', use .svg or .ppm", file)));
+    };
+    fs::write(file, image)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -150,43 +820,45 @@ mod tests {
     #[test]
     fn test_parse_path() {
         let expected = vec![
-            Move { direction: Direction::Up, steps: 1 },
-            Move { direction: Direction::Right, steps: 1 },
-            Move { direction: Direction::Down, steps: 1 },
-            Move { direction: Direction::Left, steps: 1 },
+            Move { direction: Direction8::Up, steps: 1 },
+            Move { direction: Direction8::Right, steps: 1 },
+            Move { direction: Direction8::Down, steps: 1 },
+            Move { direction: Direction8::Left, steps: 1 },
         ];
 
-        let path = Path::parse(TEST_PATH);
+        let path = Path::parse(TEST_PATH).unwrap();
         assert_eq!(expected, Vec::from(path.moves));
         assert_eq!(0, path.current_steps);
-        assert_eq!((0, 0), path.position);
+        assert_eq!(Position::origin(), path.position);
     }
 
     #[test]
     fn test_parse_puzzle_input() {
-        let (fst, snd) = Path::parse_puzzle_input("U1\nD1\n").unwrap();
-
-        let fst_mv = Vec::from(fst.moves);
-        let snd_mv = Vec::from(snd.moves);
-        assert_eq!(vec![Move { direction: Direction::Up, steps: 1 }], fst_mv);
-        assert_eq!(vec![Move { direction: Direction::Down, steps: 1 }], snd_mv);
-                   
+        let paths = Path::parse_puzzle_input("U1\nD1\n\nL1\n").unwrap();
+        let moves: Vec<Vec<Move>> = paths.into_iter().map(|path| Vec::from(path.moves)).collect();
+        assert_eq!(vec![
+            vec![Move { direction: Direction8::Up, steps: 1 }],
+            vec![Move { direction: Direction8::Down, steps: 1 }],
+            vec![Move { direction: Direction8::Left, steps: 1 }],
+        ], moves);
     }
 
     #[test]
     fn test_iter_path_1() {
-        let expected = vec![(0, 1), (1, 1), (1, 0), (0, 0)];
+        let expected: Vec<Position> = vec![(0, 1), (1, 1), (1, 0), (0, 0)]
+            .into_iter().map(Point::from).collect();
 
-        let path = Path::parse(TEST_PATH);
+        let path = Path::parse(TEST_PATH).unwrap();
         let positions: Vec<Position> = path.collect();
         assert_eq!(expected, positions);
     }
 
     #[test]
     fn test_iter_path_2() {
-        let expected = vec![(0, 1), (0, 2), (0, 3), (0, 4)];
+        let expected: Vec<Position> = vec![(0, 1), (0, 2), (0, 3), (0, 4)]
+            .into_iter().map(Point::from).collect();
 
-        let path = Path::parse("U4");
+        let path = Path::parse("U4").unwrap();
         let positions: Vec<Position> = path.collect();
         assert_eq!(expected, positions);
     }
@@ -194,23 +866,56 @@ mod tests {
     #[test]
     fn test_collect_positions() {
         let mut expected = HashMap::new();
-        expected.insert((0, 1), 1);
-        expected.insert((0, 2), 2);
-        expected.insert((1, 2), 3);
-        expected.insert((1, 1), 4);
-        expected.insert((-1, 1), 6);
+        expected.insert(Point::from((0, 1)), 1);
+        expected.insert(Point::from((0, 2)), 2);
+        expected.insert(Point::from((1, 2)), 3);
+        expected.insert(Point::from((1, 1)), 4);
+        expected.insert(Point::from((-1, 1)), 6);
         
-        let mut path = Path::parse("U2,R1,D1,L2");
-        assert_eq!(expected, path.collect_positions());
+        let mut path = Path::parse("U2,R1,D1,L2").unwrap();
+        assert_eq!(expected, path.clone().collect_positions(Visit::First));
+
+        // (0, 1) again at step 5
+        expected.insert(Point::from((0, 1)), 5);
+        assert_eq!(expected, path.collect_positions(Visit::Latest));
     }
 
-    #[test]
-    fn test_aoc_example_1() {
-        let (mut path_1, mut path_2) = Path::parse_puzzle_input("\
+    const EXAMPLE_1: &str = "\
 R75,D30,R83,U83,L12,D49,R71,U7,L72
 U62,R66,U55,R34,D71,R55,D58,R83
-").unwrap();
-        let inter = intersections(&path_1.collect_positions(), &path_2.collect_positions());
+";
+
+    const EXAMPLE_2: &str = "\
+R98,U47,R26,D63,R33,U87,L62,D20,R33,U53,R51
+U98,R91,D20,R16,D67,R40,U7,R15,U6,R7
+";
+
+    const THREE_WIRES: &str = "\
+R8,U5,L5,D3
+U7,R6,D4,L4
+U2,R6,U5,L5
+";
+
+    fn wires(input: &str, algorithm: Algorithm) -> Wires {
+        Wires::new(Path::parse_puzzle_input(input).unwrap(), algorithm, Visit::First)
+    }
+
+    // Crossings found by both algorithms, which must agree
+    fn both_algorithms_visiting(input: &str, visit: Visit) -> Wires {
+        let paths = Path::parse_puzzle_input(input).unwrap();
+        let cells = Wires::new(paths.clone(), Algorithm::Cells, visit);
+        let segments = Wires::new(paths, Algorithm::Segments, visit);
+        assert_eq!(cells.crossings, segments.crossings);
+        cells
+    }
+
+    fn both_algorithms(input: &str) -> Wires {
+        both_algorithms_visiting(input, Visit::First)
+    }
+
+    #[test]
+    fn test_aoc_example_1() {
+        let inter = wires(EXAMPLE_1, Algorithm::Cells).common();
 
         assert_eq!(Some(159), closest_distance(&inter));
         assert_eq!(Some(610), shortest_path(&inter));
@@ -218,14 +923,381 @@ U62,R66,U55,R34,D71,R55,D58,R83
 
     #[test]
     fn test_aoc_example_2() {
-        let (mut path_1, mut path_2) = Path::parse_puzzle_input("\
-R98,U47,R26,D63,R33,U87,L62,D20,R33,U53,R51
-U98,R91,D20,R16,D67,R40,U7,R15,U6,R7
-").unwrap();
-        let inter = intersections(&path_1.collect_positions(), &path_2.collect_positions());
+        let inter = wires(EXAMPLE_2, Algorithm::Cells).common();
 
         assert_eq!(Some(135), closest_distance(&inter));
         assert_eq!(Some(410), shortest_path(&inter));
     }
-}
 
+    #[test]
+    fn test_options() {
+        let args: Vec<String> = vec!["--algorithm".to_string(), "cells".to_string()];
+        assert_eq!(Algorithm::Cells, Options::parse(&args).unwrap().algorithm);
+        assert_eq!(Algorithm::Segments, Options::parse(&[]).unwrap().algorithm);
+        assert!(Options::parse(&args[..1]).is_err());
+        assert!(Options::parse(&["--fast".to_string()]).is_err());
+
+        let args: Vec<String> = vec!["--render".to_string(), "wires.svg".to_string()];
+        assert_eq!(Some("wires.svg".to_string()), Options::parse(&args).unwrap().render);
+
+        let args: Vec<String> = ["--sort", "steps", "--top", "3"].iter().map(|s| s.to_string()).collect();
+        let options = Options::parse(&args).unwrap();
+        assert_eq!((Some(Metric::Steps), Some(3)), (options.sort, options.top));
+        assert!(Options::parse(&args[..1]).is_err());
+        assert!(Options::parse(&["--sort".to_string(), "x".to_string()]).is_err());
+        assert!(Options::parse(&["--top".to_string(), "x".to_string()]).is_err());
+
+        let args: Vec<String> = ["--loops", "--visit", "latest"].iter().map(|s| s.to_string()).collect();
+        let options = Options::parse(&args).unwrap();
+        assert_eq!((Visit::Latest, true), (options.visit, options.loops));
+        assert!(Options::parse(&args[1..2]).is_err());
+        assert!(Options::parse(&["--visit".to_string(), "last".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_segments() {
+        let path = Path::parse("R3,U0,D2,L1").unwrap();
+        let expected = vec![
+            Segment { start: Point::new(0, 0), direction: Direction8::Right, length: 3, steps: 0 },
+            Segment { start: Point::new(3, 0), direction: Direction8::Down, length: 2, steps: 3 },
+            Segment { start: Point::new(3, -2), direction: Direction8::Left, length: 1, steps: 5 },
+        ];
+        assert_eq!(expected, path.segments());
+        assert_eq!((1, 3), expected[0].levels(Axis::Vertical));
+        assert_eq!((0, 0), expected[0].levels(Axis::Horizontal));
+        assert_eq!((-2, -1), expected[1].levels(Axis::Horizontal));
+        assert_eq!((4, 5), expected[1].levels(Axis::Rising));
+        assert_eq!((1, 2), expected[1].levels(Axis::Falling));
+    }
+
+    #[test]
+    fn test_segment_algorithm() {
+        let inter = both_algorithms(EXAMPLE_1).common();
+        assert_eq!(Some(159), closest_distance(&inter));
+        assert_eq!(Some(610), shortest_path(&inter));
+
+        let inter = both_algorithms(EXAMPLE_2).common();
+        assert_eq!(Some(135), closest_distance(&inter));
+        assert_eq!(Some(410), shortest_path(&inter));
+    }
+
+    #[test]
+    fn test_segment_algorithm_overlaps() {
+        // Runs along the same lines, in both directions, and back to the origin
+        let inter = both_algorithms("R8,U5,L5,D3,L3,D2\nU3,R6,D4,L9,U1,R4,L2\n").common();
+        assert_eq!(Some(0), closest_distance(&inter));
+        let inter = both_algorithms("L4,R8,U2,D4\nR6,L12,D2\n").common();
+        assert_eq!(Some(0), closest_distance(&inter));
+    }
+
+    #[test]
+    fn test_segment_algorithm_puzzle_input() {
+        let inter = both_algorithms(include_str!("../../data/2019/day_03.txt")).common();
+        assert_eq!(Some(227), closest_distance(&inter));
+        assert_eq!(Some(20286), shortest_path(&inter));
+    }
+
+    #[test]
+    fn test_two_wires_pairwise() {
+        let wires = both_algorithms(EXAMPLE_1);
+        assert_eq!(wires.common(), wires.pairwise());
+        assert_eq!(wires.common(), wires.pair(1, 0));
+    }
+
+    #[test]
+    fn test_three_wires() {
+        let wires = both_algorithms(THREE_WIRES);
+        assert_eq!(3, wires.count);
+
+        let common = wires.common();
+        assert_eq!(vec![(&Point::new(6, 5), &41)], common.iter().collect::<Vec<_>>());
+        assert_eq!(Some(11), closest_distance(&common));
+        assert_eq!(Some(41), shortest_path(&common));
+
+        // The second and third wires leave the origin along the same line
+        let pairwise = wires.pairwise();
+        assert_eq!(Some(1), closest_distance(&pairwise));
+        assert_eq!(Some(2), shortest_path(&pairwise));
+        assert_eq!(Some(&26), pairwise.get(&Point::new(6, 5)));
+        assert_eq!(Some(&30), wires.pair(0, 1).get(&Point::new(6, 5)));
+        assert_eq!((2, 2, 12), (wires.pair(0, 1).len(), wires.pair(0, 2).len(), wires.pair(1, 2).len()));
+    }
+
+    #[test]
+    fn test_long_wires() {
+        // Longer than the old 16-bit coordinates and step counts allowed
+        let wires = both_algorithms("R40000,U40000,L80000\nU60000,R20000,D100000\n");
+        let inter = wires.common();
+        assert_eq!(3, inter.len());
+        assert_eq!(Some(&200000), inter.get(&Point::new(20000, 40000)));
+        assert_eq!(Some(20000), closest_distance(&inter));
+        assert_eq!(Some(160000), shortest_path(&inter));
+    }
+
+    #[test]
+    fn test_very_long_wires() {
+        let input = "R2000000000,U2000000000\nU2000000000,R2000000000\n";
+        let inter = wires(input, Algorithm::Segments).common();
+        assert_eq!(Some(4_000_000_000), closest_distance(&inter));
+        assert_eq!(Some(8_000_000_000), shortest_path(&inter));
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let err = Path::parse("R2147483647,U1,R1").unwrap_err();
+        assert_eq!("move 3 'R1' leaves the representable range", err.to_string());
+        assert!(Path::parse("L2147483647,L1").is_ok());
+        assert!(Path::parse("L2147483647,L2").is_err());
+
+        let err = Path::parse("U2147483647,D2147483647,U2147483647").unwrap_err();
+        assert_eq!("move 3 'U2147483647' makes the wire longer than 4294967295 steps", err.to_string());
+
+        let err = Path::parse_puzzle_input("U1\nR2147483647,R1\n").unwrap_err();
+        assert!(err.to_string().starts_with("wire 2: move 2"));
+    }
+
+    fn example_drawing(input: &str) -> (Vec<Vec<Point<i64>>>, Vec<Mark>) {
+        let paths = Path::parse_puzzle_input(input).unwrap();
+        let wires = paths.iter()
+            .map(|path| path.corners().iter().map(|p| p.cast()).collect())
+            .collect();
+        let marks = marks(&Wires::new(paths, Algorithm::Segments, Visit::First).common());
+        (wires, marks)
+    }
+
+    #[test]
+    fn test_corners() {
+        let corners = Path::parse("R3,U0,D2,L1").unwrap().corners();
+        assert_eq!(vec![Point::new(0, 0), Point::new(3, 0), Point::new(3, -2), Point::new(2, -2)], corners);
+    }
+
+    #[test]
+    fn test_marks() {
+        let (_, marks) = example_drawing(EXAMPLE_1);
+        let count = |colour| marks.iter().filter(|m| m.1 == colour).count();
+        assert_eq!((4, 1, 1, 1), (count(CROSSING_COLOUR), count(CLOSEST_COLOUR), count(SHORTEST_COLOUR), count(ORIGIN_COLOUR)));
+        assert_eq!((Point::new(155, 4), CLOSEST_COLOUR, 2), marks[4]);
+        assert_eq!((Point::origin(), ORIGIN_COLOUR, 2), marks[6]);
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let (wires, marks) = example_drawing(EXAMPLE_1);
+        let svg = render_svg(&wires, &marks);
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="1000" viewBox="-5 -122 248 157">"#));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(2, svg.matches("<polyline").count());
+        assert_eq!(7, svg.matches("<circle").count());
+        assert!(svg.contains(r#"<polyline points="0,0 75,0 75,30 158,30 158,-53 146,-53 146,-4 217,-4 217,-11 145,-11" "#));
+        assert!(svg.contains(r##"<circle cx="155" cy="-4" r="2.38" fill="#d62728"/>"##));
+    }
+
+    #[test]
+    fn test_render_ppm() {
+        let (wires, marks) = example_drawing("R4,U4\nU4,R4\n");
+        let ppm = render_ppm(&wires, &marks);
+        let header = "P6\n1020 1020\n255\n";
+        assert!(ppm.starts_with(header.as_bytes()));
+        assert_eq!(header.len() + 1020 * 1020 * 3, ppm.len());
+
+        let pixel = |x: usize, y: usize| {
+            let offset = header.len() + (y * 1020 + x) * 3;
+            (ppm[offset], ppm[offset + 1], ppm[offset + 2])
+        };
+        assert_eq!((255, 255, 255), pixel(0, 0));
+        assert_eq!(ORIGIN_COLOUR, pixel(10, 1009));
+        assert_eq!(WIRE_COLOURS[0], pixel(500, 1009));
+        assert_eq!(WIRE_COLOURS[1], pixel(10, 500));
+        assert_eq!(SHORTEST_COLOUR, pixel(1009, 10));
+    }
+
+    #[test]
+    fn test_render_unknown_format() {
+        let paths = vec![Path::parse("R1").unwrap().corners()];
+        assert!(render("wires.png", &paths, &PosMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let wires = both_algorithms(EXAMPLE_1);
+        let report = wires.report(Metric::Distance, None);
+        let positions: Vec<(i32, i32)> = report.iter().map(|i| (i.position.x, i.position.y)).collect();
+        assert_eq!(vec![(155, 4), (155, 11), (158, -12), (146, 46)], positions);
+        assert_eq!(Intersection { position: Point::new(155, 4), distance: 159, steps: vec![341, 385] }, report[0]);
+        assert_eq!(726, report[0].combined_steps());
+
+        let report = wires.report(Metric::Steps, Some(2));
+        assert_eq!(2, report.len());
+        assert_eq!((170, vec![206, 404], 610), (report[0].distance, report[0].steps.clone(), report[0].combined_steps()));
+        assert_eq!(Point::new(146, 46), report[1].position);
+
+        assert_eq!(4, wires.report(Metric::Steps, Some(10)).len());
+        assert!(wires.report(Metric::Steps, Some(0)).is_empty());
+    }
+
+    #[test]
+    fn test_report_ties() {
+        // The two crossings beside (0, 1) are as far from the origin and
+        // along the wires: the lowest position comes first
+        let wires = both_algorithms("U1,L1,R2\nR1,U1,L2\n");
+        let report = wires.report(Metric::Distance, None);
+        let positions: Vec<Position> = report.iter().map(|i| i.position).collect();
+        assert_eq!(vec![Point::new(0, 1), Point::new(-1, 1), Point::new(1, 1)], positions);
+        assert_eq!(vec![2, 4], report[1].steps);
+        assert_eq!(Point::new(-1, 1), wires.report(Metric::Steps, None)[1].position);
+    }
+
+    #[test]
+    fn test_report_three_wires() {
+        let report = both_algorithms(THREE_WIRES).report(Metric::Steps, None);
+        assert_eq!(1, report.len());
+        assert_eq!((11, vec![15, 15, 11]), (report[0].distance, report[0].steps.clone()));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |input: &str| Path::parse_puzzle_input(input).err().map(|err| err.to_string());
+        assert_eq!(Some("wire 1: move 2 'X5' can't be parsed: unknown direction 'X'".to_string()), error("R1,X5\nU1"));
+        assert_eq!(Some("wire 2: move 3 'U' can't be parsed: invalid length ''".to_string()), error("R1\n\nU1,L2,U"));
+        assert_eq!(Some("wire 1: move 1 'R-4' can't be parsed: invalid length '-4'".to_string()), error("R-4"));
+        assert_eq!(Some("wire 1: move 2 'R4x' can't be parsed: invalid length '4x'".to_string()), error("U1,R4x"));
+        assert_eq!(Some("wire 1: move 2 '' can't be parsed: empty move".to_string()), error("U1,,R4"));
+        assert_eq!(Some("wire 1: move 1 'URD3' can't be parsed: unknown direction 'URD'".to_string()), error("URD3"));
+    }
+
+    #[test]
+    fn test_extended_syntax() {
+        let err = Path::parse(" U1 , UR2,dl1, LD3 ,R0 ").unwrap_err();
+        assert_eq!("move 3 'dl1' can't be parsed: unknown direction 'dl'", err.to_string());
+
+        let path = Path::parse(" U1 , UR2,DL1, LD3 ,R0,RU1 ").unwrap();
+        let directions: Vec<Direction8> = path.moves.iter().map(|mv| mv.direction).collect();
+        assert_eq!(vec![
+            Direction8::Up, Direction8::UpRight, Direction8::DownLeft, Direction8::DownLeft, Direction8::Right,
+            Direction8::UpRight,
+        ], directions);
+        assert_eq!(0, path.moves[4].steps);
+
+        let positions: Vec<Position> = path.collect();
+        let expected: Vec<Position> = vec![(0, 1), (1, 2), (2, 3), (1, 2), (0, 1), (-1, 0), (-2, -1), (-1, 0)]
+            .into_iter().map(Point::from).collect();
+        assert_eq!(expected, positions);
+    }
+
+    #[test]
+    fn test_zero_length_moves() {
+        let path = Path::parse("R0,U2,L0,L0,R1,D0").unwrap();
+        assert_eq!(3, path.clone().count());
+        assert_eq!(vec![Point::new(0, 0), Point::new(0, 2), Point::new(1, 2)], path.corners());
+        assert_eq!(0, Path::parse("R0").unwrap().count());
+    }
+
+    #[test]
+    fn test_shared_cells() {
+        let segment = |x, y, direction, length| {
+            Segment { start: Point::new(x, y), direction, length, steps: 0 }
+        };
+        let diagonal = segment(0, 0, Direction8::UpRight, 5);
+        assert_eq!(vec![Point::new(3, 3)], diagonal.shared_cells(&segment(3, 0, Direction8::Up, 4)));
+        // Crossing between cells
+        assert!(diagonal.shared_cells(&segment(0, 3, Direction8::DownRight, 3)).is_empty());
+        assert_eq!(vec![Point::new(2, 2)], diagonal.shared_cells(&segment(0, 4, Direction8::DownRight, 3)));
+        // Overlapping, in both directions
+        let expected = vec![Point::new(3, 3), Point::new(4, 4), Point::new(5, 5)];
+        assert_eq!(expected, diagonal.shared_cells(&segment(2, 2, Direction8::UpRight, 4)));
+        assert_eq!(expected, diagonal.shared_cells(&segment(6, 6, Direction8::DownLeft, 3)));
+        assert!(diagonal.shared_cells(&segment(1, 0, Direction8::UpRight, 4)).is_empty());
+        // Same as the sorted lines for straight segments
+        let straight = segment(0, 1, Direction8::Right, 4);
+        assert_eq!(vec![Point::new(2, 1)], straight.shared_cells(&segment(2, 3, Direction8::Down, 3)));
+        assert_eq!(vec![Point::new(3, 1), Point::new(4, 1)], straight.shared_cells(&segment(2, 1, Direction8::Right, 5)));
+    }
+
+    #[test]
+    fn test_diagonal_wires() {
+        // The first diagonals pass each other between (4, 4) and (5, 5)
+        let wires = both_algorithms("UR5,DR2,L3,U4\nR3,U6,DR3,UR2\n");
+        let report = wires.report(Metric::Steps, None);
+        let found: Vec<(Position, Vec<u32>)> = report.into_iter().map(|i| (i.position, i.steps)).collect();
+        assert_eq!(vec![
+            (Point::new(3, 3), vec![3, 6]),
+            (Point::new(6, 3), vec![8, 12]),
+            (Point::new(4, 5), vec![12, 10]),
+        ], found);
+    }
+
+    #[test]
+    fn test_loops() {
+        let loops = Path::parse("R4,U2,L2,D4,R1,U1").unwrap().loops();
+        assert_eq!(vec![(Point::new(2, 0), vec![2, 10])], loops.revisited.into_iter().collect::<Vec<_>>());
+        assert_eq!(vec![Loop { position: Point::new(2, 0), start: 2, end: 10 }], loops.loops);
+        let expected: Vec<Position> = vec![(1, 0), (2, 0), (2, -1), (2, -2), (3, -2), (3, -1)]
+            .into_iter().map(Point::from).collect();
+        assert_eq!(expected, loops.loop_free);
+        assert_eq!(14, loops.steps);
+    }
+
+    #[test]
+    fn test_nested_loops() {
+        // Back and forth through the origin
+        let loops = Path::parse("R2,L4,R2").unwrap().loops();
+        let revisited: Vec<(Position, Vec<u32>)> = loops.revisited.into_iter().collect();
+        assert_eq!(vec![
+            (Point::new(-1, 0), vec![5, 7]),
+            (Point::new(0, 0), vec![0, 4, 8]),
+            (Point::new(1, 0), vec![1, 3]),
+        ], revisited);
+        let spans: Vec<(u32, u32)> = loops.loops.iter().map(|l| (l.start, l.end)).collect();
+        assert_eq!(vec![(1, 3), (0, 4), (5, 7), (4, 8)], spans);
+        assert!(loops.loop_free.is_empty());
+
+        let loops = Path::parse("UR2,L1,D1,DR2").unwrap().loops();
+        assert_eq!(vec![Loop { position: Point::new(1, 1), start: 1, end: 4 }], loops.loops);
+        assert_eq!(vec![Point::new(1, 1), Point::new(2, 0), Point::new(3, -1)], loops.loop_free);
+    }
+
+    #[test]
+    fn test_loops_puzzle_input() {
+        let paths = Path::parse_puzzle_input(include_str!("../../data/2019/day_03.txt")).unwrap();
+        let loops = paths[0].clone().loops();
+        assert_eq!((82, 82, 48597, 157231), (loops.revisited.len(), loops.loops.len(), loops.loop_free.len(), loops.steps));
+    }
+
+    #[test]
+    fn test_latest_visit() {
+        // The first wire passes (2, 0) at steps 2 and 10
+        let input = "R4,U2,L2,D4,R1,U1\nD1,R2,U2\n";
+        let first = both_algorithms_visiting(input, Visit::First).common();
+        let latest = both_algorithms_visiting(input, Visit::Latest).common();
+        assert_eq!(Some(&6), first.get(&Point::new(2, 0)));
+        assert_eq!(Some(&14), latest.get(&Point::new(2, 0)));
+        assert_eq!(Some(6), shortest_path(&first));
+        assert_eq!(Some(14), shortest_path(&latest));
+        assert_eq!(first.get(&Point::new(2, 1)), latest.get(&Point::new(2, 1)));
+
+        let paths = Path::parse_puzzle_input(include_str!("../../data/2019/day_03.txt")).unwrap();
+        let latest = Wires::new(paths, Algorithm::Segments, Visit::Latest).common();
+        assert_eq!(Some(20286), shortest_path(&latest));
+    }
+
+    #[test]
+    fn test_diagonal_index() {
+        // Zigzags crossing each other on every axis
+        let first = vec!["UR3,DR3"; 20].join(",") + ",U5,L200";
+        let second = vec!["DR2,UR2,R1"; 20].join(",") + ",D3,LU100";
+        let inter = both_algorithms(&format!("{}\n{}\n", first, second)).common();
+        assert_eq!(7, inter.len());
+        assert_eq!(Some(24), closest_distance(&inter));
+        assert_eq!(Some(48), shortest_path(&inter));
+    }
+
+    #[test]
+    fn test_single_wire_loops() {
+        let paths = Path::parse_puzzle_input("R2,L4,R2\n").unwrap();
+        assert_eq!(1, paths.len());
+        let expected = "\
+Wire 1: 3 cells visited more than once, 4 loops, loop-free path of 0 steps instead of 8
+    longest loop at (0, 0), from step 0 to step 4";
+        assert_eq!(expected, describe_loops(1, &paths[0].clone().loops()));
+    }
+}
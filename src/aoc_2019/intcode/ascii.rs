@@ -0,0 +1,245 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use super::{load_code, Code, IntCode, State, VmError};
+use crate::PuzzleError;
+
+// Consecutive ASCII values as text, or any other value as a number
+#[derive(Debug, Clone, PartialEq)]
+pub enum Chunk {
+    Text(String),
+    Value(Code),
+}
+
+// Outputs of a run, in the order the program wrote them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AsciiOutput {
+    pub chunks: Vec<Chunk>,
+}
+
+impl AsciiOutput {
+    // Drains the machine's outputs
+    pub fn take(vm: &mut IntCode) -> AsciiOutput {
+        let mut output = AsciiOutput::default();
+        while let Some(value) = vm.pop_output() {
+            output.push(value);
+        }
+        output
+    }
+
+    pub fn push(&mut self, value: Code) {
+        match self.chunks.last_mut() {
+            Some(Chunk::Text(text)) if is_ascii(value) => text.push(value as u8 as char),
+            _ if is_ascii(value) => self.chunks.push(Chunk::Text((value as u8 as char).to_string())),
+            _ => self.chunks.push(Chunk::Value(value)),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.chunks.iter()
+            .filter_map(|chunk| match chunk {
+                Chunk::Text(text) => Some(text.as_str()),
+                Chunk::Value(_) => None,
+            })
+            .collect()
+    }
+
+    pub fn values(&self) -> Vec<Code> {
+        self.chunks.iter()
+            .filter_map(|chunk| match chunk {
+                Chunk::Value(value) => Some(*value),
+                Chunk::Text(_) => None,
+            })
+            .collect()
+    }
+
+    // Prints the text as is, and numbers as [n] on lines of their own.
+    // `line_start` tells whether the previous output ended with a newline,
+    // and is updated for the next one.
+    pub fn write<W: Write>(&self, out: &mut W, line_start: &mut bool) -> io::Result<()> {
+        for chunk in &self.chunks {
+            match chunk {
+                Chunk::Text(text) => {
+                    write!(out, "{}", text)?;
+                    *line_start = text.ends_with('\n');
+                }
+                Chunk::Value(value) => {
+                    let separator = if *line_start { "" } else { "\n" };
+                    writeln!(out, "{}[{}]", separator, value)?;
+                    *line_start = true;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Text interface to a machine that reads and writes ASCII characters
+#[derive(Debug, Clone)]
+pub struct Ascii {
+    pub vm: IntCode,
+}
+
+pub fn encode_line(line: &str) -> Vec<Code> {
+    line.bytes().map(Code::from).chain(Some(10)).collect()
+}
+
+pub fn is_ascii(value: Code) -> bool {
+    (0..128).contains(&value)
+}
+
+impl Ascii {
+    pub fn new(vm: IntCode) -> Ascii {
+        Ascii { vm }
+    }
+
+    // Queues a line of input, terminated by a newline
+    pub fn push_line(&mut self, line: &str) {
+        self.vm.input.extend(encode_line(line));
+    }
+
+    pub fn take_output(&mut self) -> AsciiOutput {
+        AsciiOutput::take(&mut self.vm)
+    }
+
+    // Runs until the program halts or needs more input
+    pub fn run(&mut self) -> Result<(State, AsciiOutput), VmError> {
+        let state = self.vm.run();
+        let output = self.take_output();
+        Ok((state?, output))
+    }
+
+    // Feeds lines from `input` whenever the program waits for some, and
+    // prints everything it writes, numbers on lines of their own. Stops at
+    // halt or at the end of the input.
+    pub fn interact<R: BufRead, W: Write>(
+        &mut self, input: &mut R, out: &mut W
+    ) -> Result<State, PuzzleError> {
+        let mut line_start = true;
+        loop {
+            let state = self.vm.run();
+            self.take_output().write(out, &mut line_start)?;
+            out.flush()?;
+
+            if state? == State::Halted {
+                return Ok(State::Halted);
+            }
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(State::WaitingForInput);
+            }
+            self.push_line(line.trim_end_matches(&['\r', '\n'][..]));
+        }
+    }
+}
+
+// intcode ascii <file> [script]: lines of the script are sent before stdin's
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    let filename = args.first().ok_or_else(|| PuzzleError::new("missing program file"))?;
+    let mut ascii = Ascii::new(IntCode::new(&load_code(filename)?));
+    let mut stdout = io::stdout();
+
+    if let Some(script) = args.get(1) {
+        let contents = fs::read_to_string(script)?;
+        if ascii.interact(&mut contents.as_bytes(), &mut stdout)? == State::Halted {
+            return Ok(());
+        }
+    }
+    let stdin = io::stdin();
+    match ascii.interact(&mut stdin.lock(), &mut stdout)? {
+        State::Halted => Ok(()),
+        _ => Err(PuzzleError::new("input ended before the program halted")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+
+    // Echoes each line in upper case, and reports its length as a number
+    // offset by 1000 so it isn't mistaken for text
+    const SHOUT: &str = "
+        next:   add #0, #1000, [len]
+        read:   in [c]
+                eq [c], #10, [flag]
+                jnz [flag], #done
+                add [len], #1, [len]
+                lt [c], #97, [flag]
+                jnz [flag], #print
+                add [c], #-32, [c]
+        print:  out [c]
+                jnz #1, #read
+        done:   out #10
+                out [len]
+                eq [len], #1000, [flag]
+                jz [flag], #next
+                hlt
+        c:      data 0
+        len:    data 0
+        flag:   data 0
+    ";
+
+    fn machine() -> Ascii {
+        Ascii::new(IntCode::new(&assemble(SHOUT).unwrap()))
+    }
+
+    #[test]
+    fn test_encode_line() {
+        assert_eq!(vec![72, 105, 10], encode_line("Hi"));
+        assert_eq!(vec![10], encode_line(""));
+    }
+
+    #[test]
+    fn test_run() {
+        let mut ascii = machine();
+        let (state, output) = ascii.run().unwrap();
+        assert_eq!(State::WaitingForInput, state);
+        assert_eq!(AsciiOutput::default(), output);
+
+        ascii.push_line("north");
+        let (state, output) = ascii.run().unwrap();
+        assert_eq!(State::WaitingForInput, state);
+        assert_eq!("NORTH\n", output.text());
+        assert_eq!(vec![1005], output.values());
+    }
+
+    #[test]
+    fn test_output_order() {
+        let program = assemble("
+            out #83
+            out #58
+            out #1234
+            out #33
+            out #10
+            hlt
+        ").unwrap();
+        let mut ascii = Ascii::new(IntCode::new(&program));
+        let (_, output) = ascii.run().unwrap();
+        assert_eq!(vec![
+            Chunk::Text("S:".to_string()),
+            Chunk::Value(1234),
+            Chunk::Text("!\n".to_string()),
+        ], output.chunks);
+
+        let mut ascii = Ascii::new(IntCode::new(&program));
+        let mut out = Vec::new();
+        ascii.interact(&mut "".as_bytes(), &mut out).unwrap();
+        assert_eq!("S:\n[1234]\n!\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_interact() {
+        let mut ascii = machine();
+        let mut out = Vec::new();
+        let state = ascii.interact(&mut "take key\r\n\n".as_bytes(), &mut out).unwrap();
+        assert_eq!(State::Halted, state);
+        assert_eq!("TAKE KEY\n[1008]\n\n[1000]\n", String::from_utf8(out).unwrap());
+
+        let mut ascii = machine();
+        let mut out = Vec::new();
+        let state = ascii.interact(&mut "look\n".as_bytes(), &mut out).unwrap();
+        assert_eq!(State::WaitingForInput, state);
+        assert_eq!("LOOK\n[1004]\n", String::from_utf8(out).unwrap());
+    }
+}
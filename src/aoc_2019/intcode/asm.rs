@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use super::{Code, Instruction, Mode, Opcode};
+use crate::PuzzleError;
+
+// Source syntax, one statement per line:
+//
+//     start:  in [count]          ; labels end with a colon
+//             add [count], #-1, [count]
+//             jnz [count], #start
+//             out rb+2
+//             hlt
+//     count:  data 0, 1, start    ; raw cells, numbers or label addresses
+//
+// Operands are `[a]` (position), `#v` (immediate) or `rb+o` (relative).
+// A leading address column, as printed by the disassembler, is ignored.
+
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl AsmError {
+    fn new(line: usize, message: &str) -> AsmError {
+        AsmError { line, message: message.to_string() }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+impl From<AsmError> for PuzzleError {
+    fn from(err: AsmError) -> Self {
+        PuzzleError::new(&err.to_string())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Number(Code),
+    Label(String),
+}
+
+impl Value {
+    fn parse(token: &str) -> Result<Value, String> {
+        let token = token.trim();
+        if let Ok(number) = token.parse() {
+            Ok(Value::Number(number))
+        } else if is_identifier(token) {
+            Ok(Value::Label(token.to_string()))
+        } else {
+            Err(format!("invalid value '{}'", token))
+        }
+    }
+
+    fn resolve(&self, labels: &HashMap<String, usize>) -> Result<Code, String> {
+        match self {
+            Value::Number(number) => Ok(*number),
+            Value::Label(label) => match labels.get(label) {
+                Some(&address) => Ok(address as Code),
+                None => Err(format!("undefined label '{}'", label)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Operand {
+    mode: Mode,
+    value: Value,
+}
+
+impl Operand {
+    fn parse(token: &str) -> Result<Operand, String> {
+        let token = token.trim();
+        if let Some(inner) = token.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            Ok(Operand { mode: Mode::Position, value: Value::parse(inner)? })
+        } else if let Some(value) = token.strip_prefix('#') {
+            Ok(Operand { mode: Mode::Immediate, value: Value::parse(value)? })
+        } else if let Some(offset) = token.strip_prefix("rb") {
+            let offset = offset.trim().trim_start_matches('+');
+            let offset = if offset.is_empty() { Ok(0) } else { offset.trim().parse() };
+            match offset {
+                Ok(offset) => Ok(Operand { mode: Mode::Relative, value: Value::Number(offset) }),
+                Err(_) => Err(format!("invalid relative operand '{}'", token)),
+            }
+        } else {
+            Err(format!("invalid operand '{}'", token))
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Statement {
+    Instruction(Opcode, Vec<Operand>),
+    Data(Vec<Value>),
+}
+
+impl Statement {
+    fn parse(mnemonic: &str, args: &str) -> Result<Statement, String> {
+        let args: Vec<&str> = if args.trim().is_empty() {
+            vec![]
+        } else {
+            args.split(',').collect()
+        };
+
+        if mnemonic == "data" {
+            if args.is_empty() {
+                return Err("empty data directive".to_string());
+            }
+            let values = args.iter().map(|a| Value::parse(a)).collect::<Result<_, _>>()?;
+            return Ok(Statement::Data(values));
+        }
+
+        let opcode = match Opcode::from_mnemonic(mnemonic) {
+            Some(opcode) => opcode,
+            None => return Err(format!("unknown mnemonic '{}'", mnemonic)),
+        };
+        if args.len() != opcode.arity() {
+            return Err(format!(
+                "'{}' takes {} operands, got {}", mnemonic, opcode.arity(), args.len()
+            ));
+        }
+        let operands: Vec<Operand> = args.iter().map(|a| Operand::parse(a)).collect::<Result<_, _>>()?;
+
+        if let Some(param) = opcode.write_param() {
+            if operands[param].mode == Mode::Immediate {
+                return Err(format!("'{}' cannot write to an immediate operand", mnemonic));
+            }
+        }
+        Ok(Statement::Instruction(opcode, operands))
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction(_, operands) => operands.len() + 1,
+            Statement::Data(values) => values.len(),
+        }
+    }
+
+    fn encode(&self, labels: &HashMap<String, usize>, code: &mut Vec<Code>) -> Result<(), String> {
+        match self {
+            Statement::Instruction(opcode, operands) => {
+                let mut modes = [Mode::Position; 3];
+                for (mode, operand) in modes.iter_mut().zip(operands) {
+                    *mode = operand.mode;
+                }
+                code.push(Instruction::new(*opcode, modes).encode());
+                for operand in operands {
+                    code.push(operand.value.resolve(labels)?);
+                }
+            }
+            Statement::Data(values) => {
+                for value in values {
+                    code.push(value.resolve(labels)?);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_identifier(token: &str) -> bool {
+    let mut chars = token.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn parse_line(line: &str) -> Result<(Vec<String>, Option<Statement>), String> {
+    let mut line = line.split(';').next().unwrap_or("").trim();
+
+    // Skip the address column of a disassembly listing
+    let first = line.split_whitespace().next().unwrap_or("");
+    if !first.is_empty() && first.chars().all(|c| c.is_ascii_digit()) {
+        line = line[first.len()..].trim_start();
+    }
+
+    let mut labels = Vec::new();
+    while let Some(colon) = line.find(':') {
+        let label = line[..colon].trim();
+        if !is_identifier(label) {
+            return Err(format!("invalid label '{}'", label));
+        }
+        labels.push(label.to_string());
+        line = line[colon + 1..].trim_start();
+    }
+
+    if line.is_empty() {
+        return Ok((labels, None));
+    }
+    let (mnemonic, args) = match line.find(char::is_whitespace) {
+        Some(split) => (&line[..split], &line[split..]),
+        None => (line, ""),
+    };
+    Ok((labels, Some(Statement::parse(mnemonic, args)?)))
+}
+
+pub fn assemble(source: &str) -> Result<Vec<Code>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let (line_labels, statement) = parse_line(line)
+            .map_err(|msg| AsmError::new(line_no, &msg))?;
+
+        for label in line_labels {
+            if labels.contains_key(&label) {
+                let msg = format!("duplicate label '{}'", label);
+                return Err(AsmError::new(line_no, &msg));
+            }
+            labels.insert(label, address);
+        }
+        if let Some(statement) = statement {
+            address += statement.size();
+            statements.push((line_no, statement));
+        }
+    }
+
+    let mut code = Vec::with_capacity(address);
+    for (line_no, statement) in statements {
+        statement.encode(&labels, &mut code)
+            .map_err(|msg| AsmError::new(line_no, &msg))?;
+    }
+    Ok(code)
+}
+
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    let filename = match args.first() {
+        Some(filename) => filename,
+        None => return Err(PuzzleError::new("usage: intcode asm <file>")),
+    };
+
+    let source = fs::read_to_string(filename)?;
+    let code: Vec<String> = assemble(&source)?.iter().map(|c| c.to_string()).collect();
+    println!("{}", code.join(","));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::disasm::disassemble;
+    use super::super::parse_code;
+
+    #[test]
+    fn test_assemble_day_02_example() {
+        let source = "\
+            add [9], [10], [3]
+            mul [3], [11], [0]
+            hlt
+            data 30, 40, 50
+        ";
+        assert_eq!(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50], assemble(source).unwrap());
+    }
+
+    #[test]
+    fn test_assemble_modes_and_labels() {
+        let source = "
+            start:  in rb+1         ; read into the frame
+                    jz rb+1, #end
+                    arb rb-3
+                    jnz #1, #start
+            end:    out [value]
+                    hlt
+            value:  data end
+        ";
+        let expected = vec![203, 1, 1206, 1, 10, 209, -3, 1105, 1, 0, 4, 13, 99, 10];
+        assert_eq!(expected, assemble(source).unwrap());
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let err = assemble("hlt\nfoo [1]\n").unwrap_err();
+        assert_eq!(AsmError::new(2, "unknown mnemonic 'foo'"), err);
+
+        let err = assemble("add [1], [2]").unwrap_err();
+        assert_eq!(AsmError::new(1, "'add' takes 3 operands, got 2"), err);
+
+        let err = assemble("\n\nin #4").unwrap_err();
+        assert_eq!(AsmError::new(3, "'in' cannot write to an immediate operand"), err);
+
+        let err = assemble("out [nowhere]\nhlt").unwrap_err();
+        assert_eq!(AsmError::new(1, "undefined label 'nowhere'"), err);
+
+        let err = assemble("a: hlt\na: hlt").unwrap_err();
+        assert_eq!(AsmError::new(2, "duplicate label 'a'"), err);
+
+        let err = assemble("out 12").unwrap_err();
+        assert_eq!("line 1: invalid operand '12'", err.to_string());
+    }
+
+    #[test]
+    fn test_round_trip_disassembler() {
+        let programs = vec![
+            vec![21001, 9, 5, 3, 209, -2, 99, 42, 1, 0],
+            parse_code(include_str!("../../../data/2019/day_02.txt")),
+        ];
+        for program in programs {
+            let listing: Vec<String> = disassemble(&program, false)
+                .iter().map(|item| item.to_string()).collect();
+            assert_eq!(program, assemble(&listing.join("\n")).unwrap());
+        }
+    }
+}
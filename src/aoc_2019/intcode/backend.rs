@@ -0,0 +1,300 @@
+use std::time::Instant;
+
+use super::trace::Tracer;
+use super::vm::Decoded;
+use super::asm::assemble;
+use super::{load_code, Code, IntCode, State, VmError};
+use crate::PuzzleError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    #[default]
+    Interpreter,
+    Predecoded,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 2] = [Backend::Interpreter, Backend::Predecoded];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Interpreter => "interp",
+            Backend::Predecoded => "predecoded",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Backend> {
+        Backend::ALL.iter().find(|b| b.name() == name).copied()
+    }
+
+    pub fn runner(&self, program: &[Code]) -> Runner {
+        match self {
+            Backend::Interpreter => Runner::Interpreter,
+            Backend::Predecoded => Runner::Predecoded(Predecoded::new(program)),
+        }
+    }
+}
+
+// A backend set up for one machine. It must be kept for the whole run, so
+// that the decode cache survives while the machine waits for input.
+#[derive(Debug, Clone)]
+pub enum Runner {
+    Interpreter,
+    Predecoded(Predecoded),
+}
+
+impl Runner {
+    pub fn step(&mut self, vm: &mut IntCode) -> Result<State, VmError> {
+        match self {
+            Runner::Interpreter => vm.step(),
+            Runner::Predecoded(cache) => cache.step(vm),
+        }
+    }
+
+    // Runs until the program halts or needs more input
+    pub fn run(&mut self, vm: &mut IntCode) -> Result<State, VmError> {
+        match self {
+            Runner::Interpreter => vm.run(),
+            Runner::Predecoded(cache) => cache.run(vm),
+        }
+    }
+}
+
+// Default benchmark: a tight loop, where decoding dominates the run time
+pub(crate) const LOOP_BENCHMARK: &str = "
+    loop:   add [acc], [n], [acc]
+            mul [acc], #3, [tmp]
+            lt [tmp], #1000000, [flag]
+            jnz [flag], #skip
+            add #0, #0, [acc]
+    skip:   add [n], #-1, [n]
+            jnz [n], #loop
+            out [acc]
+            hlt
+    n:      data 100000
+    acc:    data 0
+    tmp:    data 0
+    flag:   data 0
+";
+
+// Cache of the decoded instruction at each address of the program, filled
+// the first time the address is executed. Every write into a cached
+// instruction evicts it, so self-modifying code is decoded again.
+#[derive(Debug, Clone)]
+pub struct Predecoded {
+    cache: Vec<Option<Decoded>>,
+}
+
+struct Evict<'a>(&'a mut Vec<Option<Decoded>>);
+
+impl<'a> Tracer for Evict<'a> {
+    fn write(&mut self, address: usize, _value: &Code) {
+        for start in address.saturating_sub(3)..=address {
+            if let Some(slot) = self.0.get_mut(start) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+impl Predecoded {
+    pub fn new(program: &[Code]) -> Predecoded {
+        Predecoded { cache: vec![None; program.len()] }
+    }
+
+    pub fn is_cached(&self, address: usize) -> bool {
+        matches!(self.cache.get(address), Some(Some(_)))
+    }
+
+    // Must be called after changing the machine's memory outside of `step`
+    pub fn invalidate(&mut self, address: usize) {
+        Evict(&mut self.cache).write(address, &0);
+    }
+
+    pub fn step(&mut self, vm: &mut IntCode) -> Result<State, VmError> {
+        let decoded = match self.cache.get(vm.pointer) {
+            Some(Some(decoded)) => *decoded,
+            _ => {
+                let decoded = vm.fetch()?;
+                if let Some(slot) = self.cache.get_mut(vm.pointer) {
+                    *slot = Some(decoded);
+                }
+                decoded
+            }
+        };
+        vm.execute(&mut Evict(&mut self.cache), &decoded)
+    }
+
+    pub fn run(&mut self, vm: &mut IntCode) -> Result<State, VmError> {
+        loop {
+            match self.step(vm)? {
+                State::Running => continue,
+                state => return Ok(state),
+            }
+        }
+    }
+}
+
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    let program = match args.first() {
+        Some(filename) => load_code(filename)?,
+        None => assemble(LOOP_BENCHMARK)?,
+    };
+    let runs: u32 = match args.get(1) {
+        Some(runs) => runs.parse()?,
+        None => 10,
+    };
+    let input = args.iter().skip(2).map(|v| v.parse()).collect::<Result<Vec<Code>, _>>()?;
+
+    let mut timings = Vec::new();
+    for backend in Backend::ALL.iter() {
+        let now = Instant::now();
+        for _ in 0..runs {
+            let mut computer = IntCode::new(&program);
+            computer.input.extend(&input);
+            backend.runner(&program).run(&mut computer)?;
+        }
+        let elapsed = now.elapsed().as_micros();
+        println!("{:<12} {:>10} µs", backend.name(), elapsed);
+        timings.push(elapsed.max(1));
+    }
+    println!("speedup: {:.2}x", timings[0] as f64 / timings[1] as f64);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+    use super::super::parse_code;
+
+    fn assert_same_execution(program: &[Code], input: &[Code]) {
+        let mut runs = Vec::new();
+        for backend in Backend::ALL.iter() {
+            let mut computer = IntCode::new(program);
+            computer.input.extend(input);
+            let state = backend.runner(program).run(&mut computer);
+            runs.push((state, computer));
+        }
+
+        let (state, expected) = &runs[0];
+        for (other_state, other) in &runs[1..] {
+            assert_eq!(state, other_state);
+            assert_eq!(expected.memory, other.memory);
+            assert_eq!(expected.output, other.output);
+            assert_eq!(expected.pointer, other.pointer);
+            assert_eq!(expected.relative_base, other.relative_base);
+        }
+    }
+
+    #[test]
+    fn test_backend_names() {
+        for backend in Backend::ALL.iter() {
+            assert_eq!(Some(*backend), Backend::from_name(backend.name()));
+        }
+        assert_eq!(None, Backend::from_name("jit"));
+    }
+
+    #[test]
+    fn test_runner_keeps_cache() {
+        let program = assemble("
+            loop:   in [x]
+                    out [x]
+                    jnz [x], #loop
+                    hlt
+            x:      data 0
+        ").unwrap();
+        let mut computer = IntCode::new(&program);
+        let mut runner = Backend::Predecoded.runner(&program);
+        for value in 1..4 {
+            assert_eq!(Ok(State::WaitingForInput), runner.run(&mut computer));
+            computer.push_input(value);
+        }
+        match &runner {
+            Runner::Predecoded(cache) => assert!(cache.is_cached(0) && cache.is_cached(2)),
+            Runner::Interpreter => unreachable!(),
+        }
+        computer.push_input(0);
+        assert_eq!(Ok(State::Halted), runner.run(&mut computer));
+        assert_eq!(vec![1, 2, 3, 0], computer.output.iter().copied().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_differential_day_02() {
+        let program = parse_code(include_str!("../../../data/2019/day_02.txt"));
+        for noun in (0..100).step_by(7) {
+            for verb in (0..100).step_by(11) {
+                let mut program = program.clone();
+                program[1] = noun;
+                program[2] = verb;
+                assert_same_execution(&program, &[]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_differential_day_05_example() {
+        let program = [
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36,
+            98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000,
+            1, 20, 4, 20, 1105, 1, 46, 98, 99,
+        ];
+        for input in 5..12 {
+            assert_same_execution(&program, &[input]);
+        }
+        assert_same_execution(&program, &[]);
+    }
+
+    #[test]
+    fn test_differential_self_modifying() {
+        // The loop rewrites the operand of its own `out`: a stale cache
+        // would keep printing the first table entry
+        let program = assemble("
+            loop:   out [table]
+                    add [1], #1, [1]
+                    add [n], #-1, [n]
+                    jnz [n], #loop
+                    hlt
+            n:      data 4
+            table:  data 10, 20, 30, 40
+        ").unwrap();
+        assert_same_execution(&program, &[]);
+
+        let mut computer = IntCode::new(&program);
+        Backend::Predecoded.runner(&program).run(&mut computer).unwrap();
+        assert_eq!(vec![10, 20, 30, 40], computer.output.iter().copied().collect::<Vec<_>>());
+
+        // Overwrites the next instruction with `hlt`
+        assert_same_execution(&[1101, 0, 99, 4, 1, 0, 0, 0], &[]);
+    }
+
+    #[test]
+    fn test_differential_errors() {
+        assert_same_execution(&[1, 0, -20, 3, 99], &[]);
+        assert_same_execution(&[1102, Code::MAX, 2, 0, 99], &[]);
+        assert_same_execution(&[42], &[]);
+    }
+
+    #[test]
+    fn test_invalidate() {
+        // mem[5] = mem[6] + mem[6]
+        let program = [1, 6, 6, 5, 99, 0, 1];
+        let mut cache = Predecoded::new(&program);
+
+        let mut computer = IntCode::new(&program);
+        cache.run(&mut computer).unwrap();
+        assert_eq!(2, computer.memory[5]);
+
+        // The cached instruction still reads mem[6] until invalidated
+        let mut computer = IntCode::new(&program);
+        computer.memory[1] = 4;
+        cache.run(&mut computer).unwrap();
+        assert_eq!(2, computer.memory[5]);
+
+        let mut computer = IntCode::new(&program);
+        computer.memory[1] = 4;
+        cache.invalidate(1);
+        cache.run(&mut computer).unwrap();
+        assert_eq!(100, computer.memory[5]);
+    }
+}
@@ -0,0 +1,208 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+
+use super::asm::assemble;
+use super::backend::LOOP_BENCHMARK;
+use super::{load_code, Code, IntCode, State, VmError};
+use crate::PuzzleError;
+
+// Integer type of the machine's memory cells and registers
+pub trait Cell: Clone + Default + PartialEq + PartialOrd + fmt::Debug + fmt::Display + FromStr {
+    const NAME: &'static str;
+
+    fn from_i64(value: i64) -> Option<Self>;
+    fn to_i64(&self) -> Option<i64>;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    fn from_bool(value: bool) -> Self {
+        Self::from_i64(value as i64).unwrap()
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // Closest `Code` value, to report the cell in errors
+    fn to_code_lossy(&self) -> Code {
+        match self.to_i64() {
+            Some(value) => value,
+            None if *self < Self::default() => Code::MIN,
+            None => Code::MAX,
+        }
+    }
+}
+
+macro_rules! primitive_cell {
+    ($type:ty, $name:expr) => {
+        impl Cell for $type {
+            const NAME: &'static str = $name;
+
+            fn from_i64(value: i64) -> Option<Self> {
+                <$type>::try_from(value).ok()
+            }
+
+            fn to_i64(&self) -> Option<i64> {
+                i64::try_from(*self).ok()
+            }
+
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$type>::checked_add(*self, *other)
+            }
+
+            fn checked_mul(&self, other: &Self) -> Option<Self> {
+                <$type>::checked_mul(*self, *other)
+            }
+        }
+    };
+}
+
+primitive_cell!(i32, "i32");
+primitive_cell!(i64, "i64");
+primitive_cell!(i128, "i128");
+
+#[cfg(feature = "bigint")]
+mod bigint {
+    use num_bigint::BigInt;
+    use std::convert::TryFrom;
+
+    use super::Cell;
+
+    impl Cell for BigInt {
+        const NAME: &'static str = "bigint";
+
+        fn from_i64(value: i64) -> Option<Self> {
+            Some(BigInt::from(value))
+        }
+
+        fn to_i64(&self) -> Option<i64> {
+            i64::try_from(self).ok()
+        }
+
+        fn checked_add(&self, other: &Self) -> Option<Self> {
+            Some(self + other)
+        }
+
+        fn checked_mul(&self, other: &Self) -> Option<Self> {
+            Some(self * other)
+        }
+    }
+}
+
+pub fn parse_cells<C: Cell>(contents: &str) -> Vec<C> {
+    contents.split(',').filter_map(|value| value.trim().parse().ok()).collect()
+}
+
+// Converts a program to a narrower or wider cell type
+pub fn convert<C: Cell>(program: &[Code]) -> Option<Vec<C>> {
+    program.iter().map(|&value| C::from_i64(value)).collect()
+}
+
+fn time_width<C: Cell>(program: &[Code], runs: u32) -> Result<(), PuzzleError> {
+    let program = match convert::<C>(program) {
+        Some(program) => program,
+        None => {
+            println!("{:<8} program doesn't fit", C::NAME);
+            return Ok(());
+        }
+    };
+    let now = Instant::now();
+    let mut result = Ok(State::Halted);
+    for _ in 0..runs {
+        result = IntCode::new(&program).run();
+    }
+    let elapsed = now.elapsed().as_micros();
+    match result {
+        Ok(_) => println!("{:<8} {:>10} µs", C::NAME, elapsed),
+        Err(err) => println!("{:<8} {:>10} µs ({})", C::NAME, elapsed, err),
+    }
+    Ok(())
+}
+
+// intcode widths [file] [runs=10]: times the interpreter with each cell type
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    let program = match args.first() {
+        Some(filename) => load_code(filename)?,
+        None => assemble(LOOP_BENCHMARK)?,
+    };
+    let runs: u32 = match args.get(1) {
+        Some(runs) => runs.parse()?,
+        None => 10,
+    };
+    time_width::<i32>(&program, runs)?;
+    time_width::<i64>(&program, runs)?;
+    time_width::<i128>(&program, runs)?;
+    #[cfg(feature = "bigint")]
+    time_width::<num_bigint::BigInt>(&program, runs)?;
+    Ok(())
+}
+
+// Runs a program without input, returning its outputs
+pub fn run_outputs<C: Cell>(program: &[C]) -> Result<Vec<C>, VmError> {
+    let mut vm = IntCode::new(program);
+    vm.run()?;
+    Ok(vm.output.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Doubles its output 100 times, starting from 1
+    const POWER_OF_TWO: &str = "
+        loop:   mul [x], #2, [x]
+                add [n], #-1, [n]
+                jnz [n], #loop
+                out [x]
+                hlt
+        n:      data 100
+        x:      data 1
+    ";
+
+    fn power_of_two<C: Cell>() -> Result<Vec<C>, VmError> {
+        run_outputs(&convert::<C>(&assemble(POWER_OF_TWO).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_day_09_examples() {
+        let program = parse_cells::<i64>("104,1125899906842624,99");
+        assert_eq!(Ok(vec![1125899906842624]), run_outputs(&program));
+
+        let program = parse_cells::<i32>("1102,34915192,34915192,7,4,7,99,0");
+        assert_eq!(Err(VmError::Overflow { pointer: 0 }), run_outputs(&program));
+        let program = parse_cells::<i64>("1102,34915192,34915192,7,4,7,99,0");
+        assert_eq!(Ok(vec![1219070632396864]), run_outputs(&program));
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(None, convert::<i32>(&[1, 1 << 40]));
+        assert_eq!(Some(vec![1i128, 1 << 40]), convert::<i128>(&[1, 1 << 40]));
+    }
+
+    #[test]
+    fn test_wide_cells() {
+        assert_eq!(Err(VmError::Overflow { pointer: 0 }), power_of_two::<i64>());
+        assert_eq!(Ok(vec![1i128 << 100]), power_of_two::<i128>());
+    }
+
+    #[test]
+    fn test_lossy_errors() {
+        let program: Vec<i128> = vec![4, 1 << 100, 99];
+        let err = VmError::InvalidAddress { pointer: 0, address: Code::MAX };
+        assert_eq!(Err(err), run_outputs(&program));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_bigint() {
+        use num_bigint::BigInt;
+
+        let program = assemble(&POWER_OF_TWO.replace("data 100", "data 300")).unwrap();
+        let output = run_outputs(&convert::<BigInt>(&program).unwrap()).unwrap();
+        assert_eq!(vec![BigInt::from(1) << 300], output);
+        assert!(run_outputs(&convert::<i128>(&program).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use super::disasm::{decode_at, reachable, successors, Item};
+use super::{load_code, Code, Mode, Opcode};
+use crate::PuzzleError;
+
+// A run of instructions only entered at its first address
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub start: usize,
+    pub items: Vec<Item>,
+    pub successors: Vec<usize>,
+    // Ends with a jump to an address read from memory or the relative base
+    pub indirect: bool,
+}
+
+impl Block {
+    // Address after the last instruction
+    pub fn end(&self) -> usize {
+        self.items.last().map_or(self.start, |i| i.address() + i.size())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub code: bool,
+}
+
+// An instruction that writes into a reachable instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CodeWrite {
+    pub address: usize,
+    pub target: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+    pub indirect_jumps: Vec<usize>,
+    pub code_writes: Vec<CodeWrite>,
+    code: Vec<bool>,
+}
+
+fn is_jump(opcode: Opcode) -> bool {
+    opcode == Opcode::JumpIfTrue || opcode == Opcode::JumpIfFalse
+}
+
+fn static_write(item: &Item) -> Option<usize> {
+    match item {
+        Item::Instruction { opcode, operands, .. } => {
+            let operand = operands[opcode.write_param()?];
+            match operand.mode {
+                Mode::Position if operand.value >= 0 => Some(operand.value as usize),
+                _ => None,
+            }
+        }
+        Item::Data { .. } => None,
+    }
+}
+
+impl Cfg {
+    // Builds the graph of the code reachable from address 0, following
+    // jumps with immediate targets only
+    pub fn build(program: &[Code]) -> Cfg {
+        let reached = reachable(program);
+        let items: BTreeMap<usize, Item> = (0..program.len())
+            .filter(|&a| reached[a])
+            .filter_map(|a| decode_at(program, a).map(|item| (a, item)))
+            .collect();
+
+        let mut code = vec![false; program.len()];
+        for item in items.values() {
+            for cell in &mut code[item.address()..item.address() + item.size()] {
+                *cell = true;
+            }
+        }
+
+        let mut leaders: BTreeSet<usize> = BTreeSet::new();
+        leaders.insert(0);
+        let mut indirect_jumps = Vec::new();
+        for item in items.values() {
+            if let Item::Instruction { address, opcode, operands } = item {
+                if is_jump(*opcode) {
+                    leaders.extend(successors(item));
+                    if operands[1].mode != Mode::Immediate {
+                        indirect_jumps.push(*address);
+                    }
+                }
+                if *opcode == Opcode::Halt || is_jump(*opcode) {
+                    leaders.insert(address + item.size());
+                }
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in leaders.iter().filter(|a| items.contains_key(a)) {
+            let mut block = Block { start, items: vec![], successors: vec![], indirect: false };
+            let mut address = start;
+            while let Some(item) = items.get(&address) {
+                if address != start && leaders.contains(&address) {
+                    block.successors.push(address);
+                    break;
+                }
+                block.items.push(item.clone());
+                if let Item::Instruction { opcode, .. } = item {
+                    if *opcode == Opcode::Halt || is_jump(*opcode) {
+                        block.successors = successors(item);
+                        block.indirect = indirect_jumps.contains(&address);
+                        break;
+                    }
+                }
+                address += item.size();
+            }
+            blocks.insert(start, block);
+        }
+
+        let code_writes = items.values()
+            .filter_map(|item| static_write(item).map(|target| (item.address(), target)))
+            .filter(|&(_, target)| code.get(target) == Some(&true))
+            .map(|(address, target)| CodeWrite { address, target })
+            .collect();
+
+        Cfg { blocks, indirect_jumps, code_writes, code }
+    }
+
+    // Whether the cell belongs to a reachable instruction
+    pub fn is_code(&self, address: usize) -> bool {
+        self.code.get(address) == Some(&true)
+    }
+
+    // Maximal runs of code and data cells, in address order
+    pub fn regions(&self) -> Vec<Region> {
+        let mut regions: Vec<Region> = Vec::new();
+        for (address, &code) in self.code.iter().enumerate() {
+            match regions.last_mut() {
+                Some(region) if region.code == code => region.end = address + 1,
+                _ => regions.push(Region { start: address, end: address + 1, code }),
+            }
+        }
+        regions
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph intcode {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        for block in self.blocks.values() {
+            let label: String = block.items.iter().map(|i| format!("{}\\l", i)).collect();
+            let style = if block.indirect { ", color=red" } else { "" };
+            writeln!(dot, "    b{} [label=\"{}\"{}];", block.start, label, style).unwrap();
+        }
+        for block in self.blocks.values() {
+            for target in block.successors.iter().filter(|t| self.blocks.contains_key(t)) {
+                writeln!(dot, "    b{} -> b{};", block.start, target).unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    let dot = args.iter().any(|a| a == "--dot");
+    let filename = match args.iter().find(|a| !a.starts_with("--")) {
+        Some(filename) => filename,
+        None => return Err(PuzzleError::new("usage: intcode cfg <file> [--dot]")),
+    };
+
+    let cfg = Cfg::build(&load_code(filename)?);
+    if dot {
+        print!("{}", cfg.to_dot());
+        return Ok(());
+    }
+
+    for block in cfg.blocks.values() {
+        let successors: Vec<String> = block.successors.iter().map(|s| s.to_string()).collect();
+        let indirect = if block.indirect { " + indirect" } else { "" };
+        println!("block {}..{} -> [{}]{}", block.start, block.end(), successors.join(", "), indirect);
+    }
+    for region in cfg.regions() {
+        let kind = if region.code { "code" } else { "data" };
+        println!("{} {}..{}", kind, region.start, region.end);
+    }
+    for write in &cfg.code_writes {
+        println!("instruction at {} writes into code at {}", write.address, write.target);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+
+    fn starts(cfg: &Cfg) -> Vec<usize> {
+        cfg.blocks.keys().copied().collect()
+    }
+
+    #[test]
+    fn test_blocks() {
+        let program = assemble("
+            loop:   add [n], #-1, [n]
+                    jnz [n], #loop
+                    out [n]
+                    hlt
+            n:      data 3
+        ").unwrap();
+        let cfg = Cfg::build(&program);
+
+        assert_eq!(vec![0, 7], starts(&cfg));
+        assert_eq!(vec![7, 0], cfg.blocks[&0].successors);
+        assert_eq!(2, cfg.blocks[&7].items.len());
+        assert_eq!(10, cfg.blocks[&7].end());
+        assert!(cfg.blocks[&7].successors.is_empty());
+
+        let expected = vec![
+            Region { start: 0, end: 10, code: true },
+            Region { start: 10, end: 11, code: false },
+        ];
+        assert_eq!(expected, cfg.regions());
+        assert!(cfg.indirect_jumps.is_empty());
+        assert!(cfg.code_writes.is_empty());
+    }
+
+    #[test]
+    fn test_fallthrough_into_jump_target() {
+        let program = assemble("
+                    in [x]
+                    jz [x], #skip
+                    out #1
+            skip:   out #2
+                    hlt
+            x:      data 0
+        ").unwrap();
+        let cfg = Cfg::build(&program);
+
+        assert_eq!(vec![0, 5, 7], starts(&cfg));
+        assert_eq!(vec![7], cfg.blocks[&5].successors);
+    }
+
+    #[test]
+    fn test_indirect_jump_and_code_write() {
+        let program = assemble("
+                    add #0, #99, [end]
+                    jnz #1, [target]
+            end:    data 0
+            target: data 4
+        ").unwrap();
+        let cfg = Cfg::build(&program);
+
+        assert_eq!(vec![4], cfg.indirect_jumps);
+        assert!(cfg.blocks[&0].indirect);
+        assert!(cfg.blocks[&0].successors.is_empty());
+        // The `hlt` written at run time isn't visible statically
+        assert!(!cfg.is_code(7));
+        assert!(cfg.code_writes.is_empty());
+
+        let program = assemble("
+                    add #0, #2, [op]
+            op:     add #5, #6, [x]
+                    out [x]
+                    hlt
+            x:      data 0
+        ").unwrap();
+        let cfg = Cfg::build(&program);
+        assert_eq!(vec![CodeWrite { address: 0, target: 4 }], cfg.code_writes);
+    }
+
+    #[test]
+    fn test_dot() {
+        let program = assemble("loop: jnz [x], #loop\nhlt\nx: data 0").unwrap();
+        let expected = "\
+digraph intcode {
+    node [shape=box, fontname=\"monospace\"];
+    b0 [label=\"    0  jnz [4], #0\\l\"];
+    b3 [label=\"    3  hlt\\l\"];
+    b0 -> b3;
+    b0 -> b0;
+}
+";
+        assert_eq!(expected, Cfg::build(&program).to_dot());
+    }
+}
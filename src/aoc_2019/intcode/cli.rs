@@ -0,0 +1,270 @@
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+
+use super::ascii::{encode_line, AsciiOutput};
+use super::{parse_code, Backend, Code, Guard, IntCode, State};
+use crate::PuzzleError;
+
+pub const USAGE: &str = "\
+usage: intcode [options] [file]
+
+Runs an IntCode program, read from the file or from stdin if there is none.
+When the program is read from a file, stdin provides any input the program
+still needs after the --input values, as numbers separated by commas, or
+as lines of text with --ascii.
+
+options:
+  --set <addr>=<value>   change a memory cell before running
+  --input <v>[,<v>...]   queue input values
+  --ascii                read stdin and print outputs as text (non-ASCII
+                         outputs are printed as [n] on lines of their own)
+  --dump <addr>[..<end>] print memory cells after the program halts
+  --budget <n>           abort after n instructions
+  --backend <name>       interp (default) or predecoded";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub file: Option<String>,
+    pub patches: Vec<(usize, Code)>,
+    pub input: Vec<Code>,
+    pub ascii: bool,
+    pub dumps: Vec<(usize, usize)>,
+    pub budget: Option<u64>,
+    pub backend: Backend,
+}
+
+fn parse_numbers(text: &str) -> Result<Vec<Code>, PuzzleError> {
+    let mut numbers = Vec::new();
+    for value in text.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+        numbers.push(value.parse()?);
+    }
+    Ok(numbers)
+}
+
+fn parse_patch(text: &str) -> Result<(usize, Code), PuzzleError> {
+    match text.find('=') {
+        Some(split) => Ok((text[..split].parse()?, text[split + 1..].parse()?)),
+        None => Err(PuzzleError::new(&format!("invalid --set '{}'", text))),
+    }
+}
+
+fn parse_range(text: &str) -> Result<(usize, usize), PuzzleError> {
+    match text.find("..") {
+        Some(split) => Ok((text[..split].parse()?, text[split + 2..].parse()?)),
+        None => {
+            let address = text.parse()?;
+            Ok((address, address + 1))
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Options, PuzzleError> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || match args.next() {
+                Some(value) => Ok(value),
+                None => Err(PuzzleError::new(&format!("missing value for {}", arg))),
+            };
+            match arg.as_str() {
+                "--set" => options.patches.push(parse_patch(value()?)?),
+                "--input" => options.input.extend(parse_numbers(value()?)?),
+                "--ascii" => options.ascii = true,
+                "--dump" => options.dumps.push(parse_range(value()?)?),
+                "--budget" => options.budget = Some(value()?.parse()?),
+                "--backend" => {
+                    let name = value()?;
+                    options.backend = Backend::from_name(name)
+                        .ok_or_else(|| PuzzleError::new(&format!("unknown backend '{}'", name)))?;
+                }
+                "-" => options.file = None,
+                flag if flag.starts_with("--") => {
+                    return Err(PuzzleError::new(&format!("unknown option {}", flag)));
+                }
+                file => options.file = Some(file.to_string()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+// Prints the outputs as numbers, or like `intcode ascii` with --ascii
+fn print_outputs<W: Write>(
+    vm: &mut IntCode, ascii: bool, out: &mut W, line_start: &mut bool
+) -> io::Result<()> {
+    if ascii {
+        return AsciiOutput::take(vm).write(out, line_start);
+    }
+    while let Some(value) = vm.pop_output() {
+        writeln!(out, "{}", value)?;
+    }
+    Ok(())
+}
+
+// Runs the program to completion, reading missing input from `input` if any
+pub fn execute<R: BufRead, W: Write>(
+    options: &Options, program: &[Code], mut input: Option<R>, out: &mut W
+) -> Result<(), PuzzleError> {
+    let mut vm = IntCode::new(program);
+    for &(address, value) in &options.patches {
+        vm.memory.set(address, value);
+    }
+    vm.input.extend(&options.input);
+
+    let mut runner = options.backend.runner(vm.memory.as_slice());
+    let mut guard = match options.budget {
+        Some(budget) => Guard::new().budget(budget),
+        None => Guard::new(),
+    };
+
+    let mut line_start = true;
+    loop {
+        let state = guard.run_with(&mut vm, &mut runner);
+        print_outputs(&mut vm, options.ascii, out, &mut line_start)?;
+        match state? {
+            State::Halted => break,
+            State::WaitingForInput => {
+                let mut line = String::new();
+                let read = match input.as_mut() {
+                    Some(reader) => reader.read_line(&mut line)?,
+                    None => 0,
+                };
+                if read == 0 {
+                    return Err(PuzzleError::new("the program needs more input"));
+                }
+                if options.ascii {
+                    vm.input.extend(encode_line(line.trim_end_matches(&['\r', '\n'][..])));
+                } else {
+                    vm.input.extend(parse_numbers(&line)?);
+                }
+            }
+            State::Running => return Err(PuzzleError::new("the program stopped while still running")),
+        }
+    }
+
+    for &(start, end) in &options.dumps {
+        for address in start..end {
+            writeln!(out, "[{}] = {}", address, vm.memory[address])?;
+        }
+    }
+    Ok(())
+}
+
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let options = Options::parse(args)?;
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+
+    match &options.file {
+        Some(file) => {
+            let program = parse_code(&fs::read_to_string(file)?);
+            execute(&options, &program, Some(stdin.lock()), &mut stdout)
+        }
+        None => {
+            let mut text = String::new();
+            stdin.lock().read_to_string(&mut text)?;
+            execute(&options, &parse_code(&text), None::<io::StdinLock>, &mut stdout)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+    use super::super::ascii::Ascii;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    fn run(line: &str, program: &[Code], stdin: &str) -> Result<String, PuzzleError> {
+        let options = Options::parse(&args(line))?;
+        let mut out = Vec::new();
+        execute(&options, program, Some(stdin.as_bytes()), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = Options::parse(&args(
+            "--set 1=12 --set 2=2 prog.txt --input 1,2 --input 3 --dump 0 --dump 4..6 --ascii"
+        )).unwrap();
+        assert_eq!(Options {
+            file: Some("prog.txt".to_string()),
+            patches: vec![(1, 12), (2, 2)],
+            input: vec![1, 2, 3],
+            ascii: true,
+            dumps: vec![(0, 1), (4, 6)],
+            budget: None,
+            backend: Backend::Interpreter,
+        }, options);
+
+        let options = Options::parse(&args("--backend predecoded")).unwrap();
+        assert_eq!(Backend::Predecoded, options.backend);
+        assert!(Options::parse(&args("--backend jit")).is_err());
+
+        assert!(Options::parse(&args("--set 1")).is_err());
+        assert!(Options::parse(&args("--input")).is_err());
+        assert!(Options::parse(&args("--verbose")).is_err());
+    }
+
+    #[test]
+    fn test_patch_and_dump() {
+        let program = [1, 0, 0, 0, 99, 5, 6];
+        assert_eq!("[0] = 11\n", run("--set 1=5 --set 2=6 --dump 0", &program, "").unwrap());
+    }
+
+    #[test]
+    fn test_input_from_args_and_stdin() {
+        let program = assemble("
+            loop:   in [x]
+                    jz [x], #end
+                    out [x]
+                    jnz #1, #loop
+            end:    hlt
+            x:      data 0
+        ").unwrap();
+        assert_eq!("1\n2\n3\n", run("--input 1,2", &program, "3\n0\n").unwrap());
+        assert_eq!("1\n2\n3\n", run("--backend predecoded --input 1", &program, "2\n3\n0\n").unwrap());
+
+        let err = run("--input 1", &program, "").unwrap_err();
+        assert_eq!("the program needs more input", err.to_string());
+    }
+
+    #[test]
+    fn test_ascii_output() {
+        let program = assemble("out #72\nout #105\nout #10\nout #1000\nhlt").unwrap();
+        assert_eq!("Hi\n[1000]\n", run("--ascii", &program, "").unwrap());
+        let mut ascii = Ascii::new(IntCode::new(&program));
+        let mut out = Vec::new();
+        ascii.interact(&mut io::empty(), &mut out).unwrap();
+        assert_eq!("Hi\n[1000]\n", String::from_utf8(out).unwrap());
+
+        let program = assemble("out #79\nout #75\nout #1000\nout #33\nhlt").unwrap();
+        assert_eq!("OK\n[1000]\n!", run("--ascii", &program, "").unwrap());
+    }
+
+    #[test]
+    fn test_ascii_input() {
+        let program = assemble("in [c]\nin [c]\nout [c]\nhlt\nc: data 0").unwrap();
+        assert_eq!("i", run("--ascii", &program, "hi\n").unwrap());
+    }
+
+    #[test]
+    fn test_budget() {
+        let program = assemble("loop: jnz #1, #loop").unwrap();
+        assert!(run("--budget 10", &program, "").is_err());
+
+        // The budget covers the whole run, not each line of input
+        let program = assemble("loop: in [x]\njnz [x], #loop\nhlt\nx: data 0").unwrap();
+        assert!(run("--budget 6", &program, "1\n1\n0\n").is_err());
+        assert!(run("--budget 7", &program, "1\n1\n0\n").is_ok());
+    }
+}
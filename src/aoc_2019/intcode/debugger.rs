@@ -0,0 +1,401 @@
+use std::collections::{BTreeSet, HashSet};
+use std::io::{self, BufRead, Write};
+
+use super::disasm::{decode_at, Item};
+use super::{load_code, Code, History, IntCode, Opcode, State};
+use crate::PuzzleError;
+
+const HELP: &str = "\
+commands:
+  step [n]             execute n instructions (default 1)
+  continue             run until a breakpoint, watchpoint, halt or missing input
+  back [n]             undo the last n instructions or uses of set (default 1)
+  writer <addr>        show the last instruction that wrote to the memory cell
+  break <addr|op>      break at an address or before any instruction with that mnemonic
+  watch <addr>         break when the memory cell changes
+  delete <addr|op>     remove a breakpoint or watchpoint
+  info                 list breakpoints and watchpoints
+  regs                 show the pointer, relative base and I/O queues
+  mem <addr> [n]       print n memory cells (default 1)
+  set <addr|ip|rb> <v> change a memory cell or register
+  input <v>...         queue values for the program's input
+  list [addr] [n]      disassemble n instructions (default 5) from addr or the pointer
+  save <file>          write a snapshot of the machine
+  load <file>          replace the machine with a saved snapshot
+  quit";
+
+// Instructions kept in the undo log
+const HISTORY_LIMIT: usize = 1_000_000;
+
+pub struct Debugger {
+    pub vm: IntCode,
+    history: History,
+    breakpoints: BTreeSet<usize>,
+    opcode_breaks: HashSet<Opcode>,
+    watchpoints: BTreeSet<usize>,
+}
+
+enum Target {
+    Address(usize),
+    Opcode(Opcode),
+}
+
+fn parse_target(token: &str) -> Option<Target> {
+    if let Ok(address) = token.parse() {
+        Some(Target::Address(address))
+    } else {
+        Opcode::from_mnemonic(token).map(Target::Opcode)
+    }
+}
+
+impl Debugger {
+    pub fn new(vm: IntCode) -> Debugger {
+        Debugger {
+            vm,
+            history: History::with_limit(HISTORY_LIMIT),
+            breakpoints: BTreeSet::new(),
+            opcode_breaks: HashSet::new(),
+            watchpoints: BTreeSet::new(),
+        }
+    }
+
+    fn location(&self) -> String {
+        self.item_at(self.vm.pointer).to_string()
+    }
+
+    fn item_at(&self, address: usize) -> Item {
+        // Enough memory to decode any instruction, even past the end of the program
+        let window: Vec<Code> = (0..4).map(|i| self.vm.memory[address + i]).collect();
+        match decode_at(&window, 0) {
+            Some(Item::Instruction { opcode, operands, .. }) => {
+                Item::Instruction { address, opcode, operands }
+            }
+            _ => Item::Data { address, value: window[0] },
+        }
+    }
+
+    fn watched(&self) -> Vec<(usize, Code)> {
+        self.watchpoints.iter()
+            .map(|&address| (address, self.vm.memory[address]))
+            .collect()
+    }
+
+    // Executes up to `limit` instructions, stopping early on any break condition
+    fn resume<W: Write>(&mut self, limit: Option<usize>, out: &mut W) -> io::Result<()> {
+        let mut steps = 0;
+        loop {
+            let before = self.watched();
+            let state = self.history.step(&mut self.vm);
+            while let Some(value) = self.vm.pop_output() {
+                writeln!(out, "output: {}", value)?;
+            }
+
+            match state {
+                Err(err) => return writeln!(out, "error: {}", err),
+                Ok(State::Halted) => return writeln!(out, "halted at {}", self.vm.pointer),
+                Ok(State::WaitingForInput) => {
+                    return writeln!(out, "waiting for input at {}", self.vm.pointer);
+                }
+                Ok(State::Running) => (),
+            }
+
+            let mut stop = false;
+            for ((address, old), (_, new)) in before.iter().zip(self.watched()) {
+                if *old != new {
+                    writeln!(out, "watchpoint {}: {} -> {}", address, old, new)?;
+                    stop = true;
+                }
+            }
+            if self.breakpoints.contains(&self.vm.pointer) {
+                writeln!(out, "breakpoint at {}", self.vm.pointer)?;
+                stop = true;
+            }
+            if let Ok(instr) = self.vm.decode() {
+                if self.opcode_breaks.contains(&instr.opcode) {
+                    writeln!(out, "breakpoint on {}", instr.opcode.mnemonic())?;
+                    stop = true;
+                }
+            }
+
+            steps += 1;
+            if stop || limit == Some(steps) {
+                return writeln!(out, "{}", self.location());
+            }
+        }
+    }
+
+    fn list<W: Write>(&self, start: usize, count: usize, out: &mut W) -> io::Result<()> {
+        let mut address = start;
+        for _ in 0..count {
+            let item = self.item_at(address);
+            let marker = if address == self.vm.pointer { "=>" } else { "  " };
+            writeln!(out, "{}{}", marker, item)?;
+            address += item.size();
+        }
+        Ok(())
+    }
+
+    // Runs one command line, returns false when the session should end
+    pub fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let numbers: Vec<Option<Code>> = words.iter().skip(1).map(|w| w.parse().ok()).collect();
+        let arg = |i: usize| numbers.get(i).copied().flatten();
+
+        match words.first().copied() {
+            None => (),
+            Some("step") | Some("s") => {
+                let count = arg(0).unwrap_or(1).max(1) as usize;
+                self.resume(Some(count), out)?;
+            }
+            Some("continue") | Some("c") => self.resume(None, out)?,
+            Some("back") | Some("bs") => {
+                let count = arg(0).unwrap_or(1).max(1);
+                let undone = (0..count).take_while(|_| self.history.step_back(&mut self.vm).is_some());
+                if undone.count() == 0 {
+                    writeln!(out, "no history")?;
+                } else {
+                    writeln!(out, "{}", self.location())?;
+                }
+            }
+            Some("writer") => match arg(0) {
+                Some(address) if address >= 0 => match self.history.last_write(address as usize) {
+                    Some(change) => {
+                        let (_, old) = change.write.unwrap();
+                        if change.edit {
+                            writeln!(out, "step {}: set by hand (was {})", change.step, old)?;
+                        } else {
+                            let item = self.item_at(change.pointer);
+                            writeln!(out, "step {}: {} (was {})", change.step, item, old)?;
+                        }
+                    }
+                    None => writeln!(out, "no write to {} in history", address)?,
+                },
+                _ => writeln!(out, "usage: writer <addr>")?,
+            },
+            Some("break") | Some("b") => match words.get(1).and_then(|w| parse_target(w)) {
+                Some(Target::Address(address)) => {
+                    self.breakpoints.insert(address);
+                }
+                Some(Target::Opcode(opcode)) => {
+                    self.opcode_breaks.insert(opcode);
+                }
+                None => writeln!(out, "usage: break <addr|op>")?,
+            },
+            Some("watch") | Some("w") => match arg(0) {
+                Some(address) if address >= 0 => {
+                    self.watchpoints.insert(address as usize);
+                }
+                _ => writeln!(out, "usage: watch <addr>")?,
+            },
+            Some("delete") | Some("d") => match words.get(1).and_then(|w| parse_target(w)) {
+                Some(Target::Address(address)) => {
+                    self.breakpoints.remove(&address);
+                    self.watchpoints.remove(&address);
+                }
+                Some(Target::Opcode(opcode)) => {
+                    self.opcode_breaks.remove(&opcode);
+                }
+                None => writeln!(out, "usage: delete <addr|op>")?,
+            },
+            Some("info") | Some("i") => {
+                let mut ops: Vec<&str> = self.opcode_breaks.iter().map(|op| op.mnemonic()).collect();
+                ops.sort_unstable();
+                writeln!(out, "breakpoints: {:?} {:?}", self.breakpoints, ops)?;
+                writeln!(out, "watchpoints: {:?}", self.watchpoints)?;
+            }
+            Some("regs") | Some("r") => {
+                writeln!(out, "ip={} rb={}", self.vm.pointer, self.vm.relative_base)?;
+                writeln!(out, "input={:?} output={:?}", self.vm.input, self.vm.output)?;
+            }
+            Some("mem") | Some("x") => match arg(0) {
+                Some(start) if start >= 0 => {
+                    let count = arg(1).unwrap_or(1).max(1) as usize;
+                    for address in (start as usize..).take(count) {
+                        writeln!(out, "{:>5}  {}", address, self.vm.memory[address])?;
+                    }
+                }
+                _ => writeln!(out, "usage: mem <addr> [n]")?,
+            },
+            Some("set") => match (words.get(1).copied(), arg(1)) {
+                (Some("ip"), Some(value)) if value >= 0 => {
+                    self.history.edit(&self.vm, None);
+                    self.vm.pointer = value as usize;
+                }
+                (Some("rb"), Some(value)) => {
+                    self.history.edit(&self.vm, None);
+                    self.vm.relative_base = value;
+                }
+                (Some(_), Some(value)) => match arg(0) {
+                    Some(address) if address >= 0 => {
+                        self.history.edit(&self.vm, Some(address as usize));
+                        self.vm.memory.set(address as usize, value);
+                    }
+                    _ => writeln!(out, "invalid address")?,
+                },
+                _ => writeln!(out, "usage: set <addr|ip|rb> <value>")?,
+            },
+            Some("input") => {
+                if numbers.is_empty() || numbers.iter().any(|n| n.is_none()) {
+                    writeln!(out, "usage: input <value>...")?;
+                } else {
+                    self.vm.input.extend(numbers.iter().flatten());
+                }
+            }
+            Some("list") | Some("l") => {
+                let start = arg(0).filter(|&a| a >= 0).map_or(self.vm.pointer, |a| a as usize);
+                let count = arg(1).unwrap_or(5).max(1) as usize;
+                self.list(start, count, out)?;
+            }
+            Some("save") => match words.get(1) {
+                Some(filename) => {
+                    if let Err(err) = self.vm.save(filename) {
+                        writeln!(out, "error: {}", err)?;
+                    }
+                }
+                None => writeln!(out, "usage: save <file>")?,
+            },
+            Some("load") => match words.get(1).map(|f| IntCode::load(f)) {
+                Some(Ok(vm)) => {
+                    self.vm = vm;
+                    self.history.clear();
+                    writeln!(out, "{}", self.location())?;
+                }
+                Some(Err(err)) => writeln!(out, "error: {}", err)?,
+                None => writeln!(out, "usage: load <file>")?,
+            },
+            Some("help") | Some("h") => writeln!(out, "{}", HELP)?,
+            Some("quit") | Some("q") => return Ok(false),
+            Some(other) => writeln!(out, "unknown command '{}', try 'help'", other)?,
+        }
+        Ok(true)
+    }
+}
+
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    let filename = match args.first() {
+        Some(filename) => filename,
+        None => return Err(PuzzleError::new("usage: intcode debug <file>")),
+    };
+
+    let mut debugger = Debugger::new(IntCode::new(&load_code(filename)?));
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+
+    writeln!(stdout, "{}", debugger.location())?;
+    loop {
+        write!(stdout, "(icdb) ")?;
+        stdout.flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 || !debugger.execute(&line, &mut stdout)? {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+
+    fn debugger(source: &str) -> Debugger {
+        Debugger::new(IntCode::new(&assemble(source).unwrap()))
+    }
+
+    fn run(debugger: &mut Debugger, commands: &[&str]) -> Vec<String> {
+        let mut out = Vec::new();
+        for command in commands {
+            debugger.execute(command, &mut out).unwrap();
+        }
+        String::from_utf8(out).unwrap().lines().map(|l| l.to_string()).collect()
+    }
+
+    const COUNTDOWN: &str = "
+        start:  out [n]
+                add [n], #-1, [n]
+                jnz [n], #start
+                hlt
+        n:      data 2
+    ";
+
+    #[test]
+    fn test_step_and_regs() {
+        let mut dbg = debugger(COUNTDOWN);
+        let out = run(&mut dbg, &["step 2", "regs"]);
+        assert_eq!(vec![
+            "output: 2",
+            "    6  jnz [10], #0",
+            "ip=6 rb=0",
+            "input=[] output=[]",
+        ], out);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut dbg = debugger(COUNTDOWN);
+        let out = run(&mut dbg, &["break 9", "break add", "continue", "delete add", "c"]);
+        assert_eq!(vec![
+            "output: 2",
+            "breakpoint on add",
+            "    2  add [10], #-1, [10]",
+            "output: 1",
+            "breakpoint at 9",
+            "    9  hlt",
+        ], out);
+        assert_eq!(0, dbg.vm.memory[10]);
+    }
+
+    #[test]
+    fn test_watchpoint_and_memory() {
+        let mut dbg = debugger(COUNTDOWN);
+        let out = run(&mut dbg, &["watch 10", "c", "set 10 0", "x 9 2", "c"]);
+        assert_eq!(vec![
+            "output: 2",
+            "watchpoint 10: 2 -> 1",
+            "    6  jnz [10], #0",
+            "    9  99",
+            "   10  0",
+            "halted at 9",
+        ], out);
+    }
+
+    #[test]
+    fn test_input() {
+        let mut dbg = debugger("in [5]\nout [5]\nhlt\ndata 0\n");
+        let out = run(&mut dbg, &["c", "input 42", "c"]);
+        assert_eq!(vec!["waiting for input at 0", "output: 42", "halted at 4"], out);
+    }
+
+    #[test]
+    fn test_reverse_step() {
+        let mut dbg = debugger(COUNTDOWN);
+        let out = run(&mut dbg, &["c", "writer 10", "back 2", "x 10", "back 10", "back"]);
+        assert_eq!(vec![
+            "output: 2",
+            "output: 1",
+            "halted at 9",
+            "step 4:     2  add [10], #-1, [10] (was 1)",
+            "    2  add [10], #-1, [10]",
+            "   10  1",
+            "    0  out [10]",
+            "no history",
+        ], out);
+        assert_eq!(2, dbg.vm.memory[10]);
+    }
+
+    #[test]
+    fn test_back_after_set() {
+        let mut dbg = debugger(COUNTDOWN);
+        let out = run(&mut dbg, &["step", "set 10 5", "set ip 9", "writer 10", "back", "back", "x 10", "back", "back"]);
+        assert_eq!(vec![
+            "output: 2",
+            "    2  add [10], #-1, [10]",
+            "step 1: set by hand (was 2)",
+            "    2  add [10], #-1, [10]",
+            "    2  add [10], #-1, [10]",
+            "   10  2",
+            "    0  out [10]",
+            "no history",
+        ], out);
+    }
+}
@@ -0,0 +1,629 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+
+use super::disasm::{decode_at, successors, Item, Operand};
+use super::{load_code, Code, Mode, Opcode};
+use crate::PuzzleError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cond {
+    pub lhs: String,
+    pub op: &'static str,
+    pub rhs: String,
+}
+
+impl Cond {
+    fn new(lhs: String, op: &'static str, rhs: String) -> Cond {
+        Cond { lhs, op, rhs }
+    }
+
+    pub fn negate(&self) -> Cond {
+        let op = match self.op {
+            "<" => ">=",
+            ">=" => "<",
+            "==" => "!=",
+            _ => "==",
+        };
+        Cond::new(self.lhs.clone(), op, self.rhs.clone())
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.op, self.rhs)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Line(String),
+    Label(usize),
+    Goto { cond: Option<Cond>, target: usize },
+    If { cond: Cond, body: Vec<Node> },
+    // Runs the body while the condition holds, forever without one
+    Loop { cond: Option<Cond>, body: Vec<Node> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub entry: usize,
+    pub body: Vec<Node>,
+}
+
+impl Function {
+    pub fn name(&self) -> String {
+        function_name(self.entry)
+    }
+}
+
+fn function_name(entry: usize) -> String {
+    if entry == 0 { "main".to_string() } else { format!("f{}", entry) }
+}
+
+fn render(operand: &Operand) -> String {
+    match operand.mode {
+        Mode::Position => format!("m[{}]", operand.value),
+        Mode::Immediate => operand.value.to_string(),
+        Mode::Relative => format!("rb[{}]", operand.value),
+    }
+}
+
+fn is_imm(operand: &Operand, value: Code) -> bool {
+    operand.mode == Mode::Immediate && operand.value == value
+}
+
+// Some(true) if the jump is always taken, Some(false) if never
+fn constant_jump(opcode: Opcode, cond: &Operand) -> Option<bool> {
+    if cond.mode == Mode::Immediate {
+        Some((cond.value != 0) == (opcode == Opcode::JumpIfTrue))
+    } else {
+        None
+    }
+}
+
+// A goto to a label in the function, or to a raw address when the target is
+// computed, negative or outside the function
+fn jump(body: &BTreeSet<usize>, cond: Option<Cond>, target: &Operand) -> Node {
+    let label = Some(target).filter(|t| t.mode == Mode::Immediate)
+        .and_then(|t| usize::try_from(t.value).ok())
+        .filter(|t| body.contains(t));
+    if let Some(target) = label {
+        return Node::Goto { cond, target };
+    }
+    let jump = match target.mode {
+        Mode::Immediate => format!("goto {}", target.value),
+        _ => format!("goto *{}", render(target)),
+    };
+    Node::Line(match cond {
+        Some(cond) => format!("if ({}) {}", cond, jump),
+        None => jump,
+    })
+}
+
+fn parts(item: &Item) -> (usize, Opcode, &[Operand]) {
+    match item {
+        Item::Instruction { address, opcode, operands } => (*address, *opcode, operands),
+        Item::Data { .. } => unreachable!(),
+    }
+}
+
+// Functions and calls found by following the relative-base calling
+// convention: a call stores its return address with immediate operands
+// into the stack frame, then jumps unconditionally; a return jumps
+// unconditionally through the relative base.
+struct Program {
+    items: BTreeMap<usize, Item>,
+    functions: BTreeMap<usize, BTreeSet<usize>>,
+    calls: HashMap<usize, usize>,
+    returns: HashSet<usize>,
+    reads: HashMap<Operand, usize>,
+}
+
+impl Program {
+    fn analyse(code: &[Code]) -> Program {
+        let mut program = Program {
+            items: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            calls: HashMap::new(),
+            returns: HashSet::new(),
+            reads: HashMap::new(),
+        };
+        let mut entries = vec![0];
+        while let Some(entry) = entries.pop() {
+            if !program.functions.contains_key(&entry) {
+                let body = program.explore(code, entry, &mut entries);
+                program.functions.insert(entry, body);
+            }
+        }
+
+        for item in program.items.values() {
+            let (_, opcode, operands) = parts(item);
+            for (index, operand) in operands.iter().enumerate() {
+                if operand.mode != Mode::Immediate && opcode.write_param() != Some(index) {
+                    *program.reads.entry(*operand).or_insert(0) += 1;
+                }
+            }
+        }
+        program
+    }
+
+    fn explore(&mut self, code: &[Code], entry: usize, entries: &mut Vec<usize>) -> BTreeSet<usize> {
+        let mut body = BTreeSet::new();
+        // Constants stored into the stack frame since the last jump
+        let mut stack: Vec<(usize, Vec<Code>)> = vec![(entry, vec![])];
+
+        while let Some((address, mut stored)) = stack.pop() {
+            if body.contains(&address) {
+                continue;
+            }
+            let item = match decode_at(code, address) {
+                Some(item) => item,
+                None => continue,
+            };
+            body.insert(address);
+            let (_, opcode, operands) = parts(&item);
+            let next = address + item.size();
+
+            match opcode {
+                Opcode::Halt => (),
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                    let always = constant_jump(opcode, &operands[0]) == Some(true);
+                    let target = operands[1];
+                    let call = match usize::try_from(target.value) {
+                        Ok(entry) if always && target.mode == Mode::Immediate
+                            && stored.contains(&(next as Code)) => Some(entry),
+                        _ => None,
+                    };
+                    if always && target.mode == Mode::Relative {
+                        self.returns.insert(address);
+                    } else if let Some(entry) = call {
+                        self.calls.insert(address, entry);
+                        entries.push(entry);
+                        stack.push((next, vec![]));
+                    } else {
+                        stack.extend(successors(&item).into_iter().map(|s| (s, vec![])));
+                    }
+                }
+                Opcode::Add | Opcode::Mul => {
+                    let (a, b, dst) = (operands[0], operands[1], operands[2]);
+                    if a.mode == Mode::Immediate && b.mode == Mode::Immediate
+                        && dst.mode == Mode::Relative
+                    {
+                        let value = match opcode {
+                            Opcode::Add => a.value.checked_add(b.value),
+                            _ => a.value.checked_mul(b.value),
+                        };
+                        stored.extend(value);
+                    }
+                    stack.push((next, stored));
+                }
+                _ => stack.push((next, stored)),
+            }
+            self.items.insert(address, item);
+        }
+        body
+    }
+
+    fn expression(&self, opcode: Opcode, a: &Operand, b: &Operand) -> String {
+        match opcode {
+            Opcode::Add if is_imm(a, 0) => render(b),
+            Opcode::Add if is_imm(b, 0) => render(a),
+            Opcode::Add if b.mode == Mode::Immediate && b.value < 0 => {
+                format!("{} - {}", render(a), b.value.unsigned_abs())
+            }
+            Opcode::Add => format!("{} + {}", render(a), render(b)),
+            Opcode::Mul if is_imm(a, 1) => render(b),
+            Opcode::Mul if is_imm(b, 1) => render(a),
+            Opcode::Mul if is_imm(b, -1) => format!("-{}", render(a)),
+            Opcode::Mul => format!("{} * {}", render(a), render(b)),
+            Opcode::LessThan => format!("{} < {}", render(a), render(b)),
+            _ => format!("{} == {}", render(a), render(b)),
+        }
+    }
+
+    // Whether execution can go on to the next instruction
+    fn falls_through(&self, address: usize) -> bool {
+        let (_, opcode, operands) = parts(&self.items[&address]);
+        let is_jump = opcode == Opcode::JumpIfTrue || opcode == Opcode::JumpIfFalse;
+        opcode != Opcode::Halt
+            && !(is_jump && constant_jump(opcode, &operands[0]) == Some(true)
+                && !self.calls.contains_key(&address))
+    }
+
+    // Jump targets inside the function that need a label, for its addresses
+    // lowered in the given order
+    fn targets(&self, body: &BTreeSet<usize>, order: &[usize]) -> BTreeSet<usize> {
+        let mut targets = BTreeSet::new();
+        for (index, &address) in order.iter().enumerate() {
+            let item = &self.items[&address];
+            let (_, opcode, operands) = parts(item);
+            let next = address + item.size();
+            let is_jump = opcode == Opcode::JumpIfTrue || opcode == Opcode::JumpIfFalse;
+
+            if is_jump && !self.calls.contains_key(&address) {
+                let target = operands[1];
+                if target.mode == Mode::Immediate && constant_jump(opcode, &operands[0]) != Some(false) {
+                    // Targets outside the function can't be labelled: the
+                    // jump fails, or runs data
+                    targets.extend(usize::try_from(target.value).ok().filter(|t| body.contains(t)));
+                }
+            }
+            if self.falls_through(address) && body.contains(&next) && order.get(index + 1) != Some(&next) {
+                targets.insert(next);
+            }
+        }
+        targets
+    }
+
+    // Flat list of statements, labels and gotos, from the entry up to the
+    // end of the function, then the addresses below the entry
+    fn lower(&self, entry: usize, body: &BTreeSet<usize>) -> Vec<Node> {
+        let order: Vec<usize> = body.range(entry..).chain(body.range(..entry)).copied().collect();
+        let targets = self.targets(body, &order);
+        let mut nodes = Vec::new();
+        // Node, destination and condition of a comparison just lowered
+        let mut compare: Option<(usize, Operand, Cond)> = None;
+
+        for (index, &address) in order.iter().enumerate() {
+            if targets.contains(&address) {
+                // A goto to the label right after it does nothing
+                if nodes.last() == Some(&Node::Goto { cond: None, target: address }) {
+                    nodes.pop();
+                }
+                nodes.push(Node::Label(address));
+                compare = None;
+            }
+            let item = &self.items[&address];
+            let (_, opcode, operands) = parts(item);
+            let next = address + item.size();
+            let last_compare = compare.take();
+
+            match opcode {
+                Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals => {
+                    let (a, b, dst) = (&operands[0], &operands[1], &operands[2]);
+                    let expr = self.expression(opcode, a, b);
+                    nodes.push(Node::Line(format!("{} = {}", render(dst), expr)));
+                    if (opcode == Opcode::LessThan || opcode == Opcode::Equals)
+                        && dst != a && dst != b
+                    {
+                        let op = if opcode == Opcode::LessThan { "<" } else { "==" };
+                        compare = Some((nodes.len() - 1, *dst, Cond::new(render(a), op, render(b))));
+                    }
+                }
+                Opcode::Input => {
+                    nodes.push(Node::Line(format!("{} = input()", render(&operands[0]))));
+                }
+                Opcode::Output => {
+                    nodes.push(Node::Line(format!("output({})", render(&operands[0]))));
+                }
+                Opcode::AdjustBase => {
+                    let line = match operands[0] {
+                        Operand { mode: Mode::Immediate, value } if value < 0 => {
+                            format!("rb -= {}", value.unsigned_abs())
+                        }
+                        operand => format!("rb += {}", render(&operand)),
+                    };
+                    nodes.push(Node::Line(line));
+                }
+                Opcode::Halt => nodes.push(Node::Line("halt".to_string())),
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                    if let Some(&entry) = self.calls.get(&address) {
+                        nodes.push(Node::Line(format!("call {}()", function_name(entry))));
+                    } else if self.returns.contains(&address) {
+                        nodes.push(Node::Line("return".to_string()));
+                    } else {
+                        let (flag, target) = (&operands[0], &operands[1]);
+                        // The comparison must be the last node: a goto after it
+                        // means the jump isn't reached from it
+                        let cond = match last_compare {
+                            Some((index, dst, cond)) if dst == *flag && index + 1 == nodes.len() => {
+                                // Drop the comparison if nothing else reads its result
+                                if self.reads.get(flag) == Some(&1) {
+                                    nodes.pop();
+                                }
+                                cond
+                            }
+                            _ => Cond::new(render(flag), "!=", "0".to_string()),
+                        };
+                        let cond = if opcode == Opcode::JumpIfTrue { cond } else { cond.negate() };
+                        match constant_jump(opcode, flag) {
+                            Some(true) => nodes.push(jump(body, None, target)),
+                            // Never taken
+                            Some(false) => (),
+                            None => nodes.push(jump(body, Some(cond), target)),
+                        }
+                    }
+                }
+            }
+
+            if self.falls_through(address) && order.get(index + 1) != Some(&next) {
+                nodes.push(if body.contains(&next) {
+                    Node::Goto { cond: None, target: next }
+                } else {
+                    Node::Line(format!("invalid instruction at {}", next))
+                });
+            }
+        }
+        nodes
+    }
+}
+
+fn goto_target(node: &Node) -> Option<usize> {
+    match node {
+        Node::Goto { target, .. } => Some(*target),
+        _ => None,
+    }
+}
+
+// Whether the labels in nodes[lo..hi] are only jumped to from that range
+fn closed(nodes: &[Node], lo: usize, hi: usize) -> bool {
+    let labels: HashSet<usize> = nodes[lo..hi].iter()
+        .filter_map(|n| match n { Node::Label(l) => Some(*l), _ => None })
+        .collect();
+    nodes.iter().enumerate().all(|(i, node)| match goto_target(node) {
+        Some(target) if labels.contains(&target) => lo <= i && i < hi,
+        _ => true,
+    })
+}
+
+// Turns backward jumps into loops, and forward conditional jumps over a
+// self-contained range into `if` blocks
+fn structure(nodes: &[Node], lo: usize, hi: usize) -> Vec<Node> {
+    let mut result = Vec::new();
+    let mut i = lo;
+    while i < hi {
+        match &nodes[i] {
+            Node::Label(label) => {
+                let back = (i + 1..hi).rev().find(|&k| goto_target(&nodes[k]) == Some(*label));
+                if let Some(k) = back.filter(|&k| closed(nodes, i + 1, k)) {
+                    let cond = match &nodes[k] {
+                        Node::Goto { cond, .. } => cond.clone(),
+                        _ => None,
+                    };
+                    result.push(nodes[i].clone());
+                    result.push(Node::Loop { cond, body: structure(nodes, i + 1, k) });
+                    i = k + 1;
+                    continue;
+                }
+            }
+            Node::Goto { cond: Some(cond), target } => {
+                let end = (i + 1..hi).find(|&j| nodes[j] == Node::Label(*target));
+                if let Some(j) = end.filter(|&j| closed(nodes, i + 1, j)) {
+                    if j > i + 1 {
+                        result.push(Node::If { cond: cond.negate(), body: structure(nodes, i + 1, j) });
+                    }
+                    i = j;
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        result.push(nodes[i].clone());
+        i += 1;
+    }
+    result
+}
+
+fn collect_targets(nodes: &[Node], targets: &mut HashSet<usize>) {
+    for node in nodes {
+        match node {
+            Node::Goto { target, .. } => {
+                targets.insert(*target);
+            }
+            Node::If { body, .. } | Node::Loop { body, .. } => collect_targets(body, targets),
+            _ => (),
+        }
+    }
+}
+
+pub fn decompile(code: &[Code]) -> Vec<Function> {
+    let program = Program::analyse(code);
+    program.functions.iter()
+        .map(|(&entry, body)| {
+            let nodes = program.lower(entry, body);
+            Function { entry, body: structure(&nodes, 0, nodes.len()) }
+        })
+        .collect()
+}
+
+fn write_nodes(
+    f: &mut fmt::Formatter, nodes: &[Node], depth: usize, labels: &HashSet<usize>
+) -> fmt::Result {
+    let indent = "    ".repeat(depth);
+    for node in nodes {
+        match node {
+            Node::Line(line) => writeln!(f, "{}{}", indent, line)?,
+            Node::Label(label) if labels.contains(label) => writeln!(f, "{}L{}:", indent, label)?,
+            Node::Label(_) => (),
+            Node::Goto { cond: Some(cond), target } => {
+                writeln!(f, "{}if ({}) goto L{}", indent, cond, target)?
+            }
+            Node::Goto { cond: None, target } => writeln!(f, "{}goto L{}", indent, target)?,
+            Node::If { cond, body } => {
+                writeln!(f, "{}if ({}) {{", indent, cond)?;
+                write_nodes(f, body, depth + 1, labels)?;
+                writeln!(f, "{}}}", indent)?;
+            }
+            Node::Loop { cond, body } => {
+                writeln!(f, "{}{}", indent, if cond.is_some() { "do {" } else { "loop {" })?;
+                write_nodes(f, body, depth + 1, labels)?;
+                match cond {
+                    Some(cond) => writeln!(f, "{}}} while ({})", indent, cond)?,
+                    None => writeln!(f, "{}}}", indent)?,
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut labels = HashSet::new();
+        collect_targets(&self.body, &mut labels);
+        writeln!(f, "fn {}() {{", self.name())?;
+        write_nodes(f, &self.body, 1, &labels)?;
+        write!(f, "}}")
+    }
+}
+
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    let filename = match args.first() {
+        Some(filename) => filename,
+        None => return Err(PuzzleError::new("usage: intcode decompile <file>")),
+    };
+    let functions = decompile(&load_code(filename)?);
+    let rendered: Vec<String> = functions.iter().map(|f| f.to_string()).collect();
+    println!("{}", rendered.join("\n\n"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+
+    fn render_all(source: &str) -> Vec<String> {
+        decompile(&assemble(source).unwrap()).iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn test_loop_and_if() {
+        let functions = render_all("
+                    in [n]
+            loop:   lt [n], #10, [flag]
+                    jz [flag], #big
+                    out [n]
+            big:    add [n], #-1, [n]
+                    jnz [n], #loop
+                    hlt
+            n:      data 0
+            flag:   data 0
+        ");
+        let expected = "\
+fn main() {
+    m[19] = input()
+    do {
+        if (m[19] < 10) {
+            output(m[19])
+        }
+        m[19] = m[19] - 1
+    } while (m[19] != 0)
+    halt
+}";
+        assert_eq!(vec![expected], functions);
+    }
+
+    #[test]
+    fn test_call_and_return() {
+        let functions = render_all("
+                    arb #stack
+                    add #ret, #0, rb+0
+                    add #5, #0, rb+1
+                    jz #0, #double
+            ret:    out rb+1
+                    hlt
+            double: mul rb+1, #2, rb+1
+                    jz #0, rb+0
+            stack:  data 0
+        ");
+        let main = "\
+fn main() {
+    rb += 23
+    rb[0] = 13
+    rb[1] = 5
+    call f16()
+    output(rb[1])
+    halt
+}";
+        let double = "\
+fn f16() {
+    rb[1] = rb[1] * 2
+    return
+}";
+        assert_eq!(vec![main, double], functions);
+    }
+
+    #[test]
+    fn test_infinite_loop_and_goto() {
+        // The second jump enters the loop from outside, so it stays a goto
+        let functions = render_all("
+                    in [x]
+                    jnz [x], #inner
+            top:    out #1
+            inner:  out #2
+                    jz #0, #top
+            x:      data 0
+        ");
+        let expected = "\
+fn main() {
+    m[12] = input()
+    if (m[12] != 0) goto L7
+    L5:
+    output(1)
+    L7:
+    output(2)
+    goto L5
+}";
+        assert_eq!(vec![expected], functions);
+
+        let functions = render_all("spin: out #1\njz #0, #spin");
+        assert_eq!(vec!["fn main() {\n    loop {\n        output(1)\n    }\n}"], functions);
+    }
+
+    #[test]
+    fn test_entry_not_first() {
+        // The function starts at `func`, after the code it jumps back to
+        let functions = render_all("
+                    arb #stack
+                    add #ret, #0, rb+0
+                    jz #0, #func
+            ret:    hlt
+            body:   out #1
+                    jz #0, rb+0
+            func:   out #0
+                    jz #0, #body
+            stack:  data 0
+        ");
+        assert_eq!("fn f15() {\n    output(0)\n    output(1)\n    return\n}", functions[1]);
+    }
+
+    #[test]
+    fn test_comparison_before_goto() {
+        // The comparison falls through to an invalid instruction, so the
+        // jump at the function's entry doesn't test its result
+        let functions = render_all("
+                    arb #stack
+                    add #ret, #0, rb+0
+                    jz #0, #func
+            ret:    hlt
+            back:   eq [x], #5, [f]
+                    data 77
+            func:   jnz [f], #out
+                    jz #0, #back
+            out:    jz #0, rb+0
+            x:      data 0
+            f:      data 0
+            stack:  data 0
+        ");
+        let expected = "\
+fn f15() {
+    if (m[25] == 0) {
+        goto L10
+    }
+    return
+    L10:
+    m[25] = m[24] == 5
+    invalid instruction at 14
+}";
+        assert_eq!(expected, functions[1]);
+    }
+
+    #[test]
+    fn test_negative_target() {
+        let functions = render_all("in [x]\njnz [x], #-4\nhlt\nx: data 0");
+        let expected = "fn main() {\n    m[6] = input()\n    if (m[6] != 0) goto -4\n    halt\n}";
+        assert_eq!(vec![expected], functions);
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt;
+
+use super::{load_code, Code, Instruction, Mode, Opcode};
+use crate::PuzzleError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Operand {
+    pub mode: Mode,
+    pub value: Code,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative if self.value < 0 => write!(f, "rb{}", self.value),
+            Mode::Relative => write!(f, "rb+{}", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Instruction { address: usize, opcode: Opcode, operands: Vec<Operand> },
+    Data { address: usize, value: Code },
+}
+
+impl Item {
+    pub fn address(&self) -> usize {
+        match self {
+            Item::Instruction { address, .. } | Item::Data { address, .. } => *address,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Item::Instruction { operands, .. } => operands.len() + 1,
+            Item::Data { .. } => 1,
+        }
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Item::Instruction { address, opcode, operands } => {
+                write!(f, "{:>5}  {}", address, opcode.mnemonic())?;
+                for (i, operand) in operands.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(f, "{}{}", sep, operand)?;
+                }
+                Ok(())
+            }
+            Item::Data { address, value } => write!(f, "{:>5}  data {}", address, value),
+        }
+    }
+}
+
+// Decodes the instruction at the address, if it is valid and fits in the program
+pub fn decode_at(program: &[Code], address: usize) -> Option<Item> {
+    let instruction = Instruction::decode(*program.get(address)?)?;
+    let params = program.get(address + 1..address + instruction.size())?;
+
+    let operands = params.iter().zip(instruction.modes.iter())
+        .map(|(&value, &mode)| Operand { mode, value })
+        .collect();
+    Some(Item::Instruction { address, opcode: instruction.opcode, operands })
+}
+
+// Addresses where execution can continue after the instruction at `address`.
+// Jumps through memory or the relative base can't be resolved statically.
+pub(crate) fn successors(item: &Item) -> Vec<usize> {
+    let (address, opcode, operands) = match item {
+        Item::Instruction { address, opcode, operands } => (*address, *opcode, operands),
+        Item::Data { .. } => return vec![],
+    };
+    let next = address + item.size();
+
+    match opcode {
+        Opcode::Halt => vec![],
+        Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+            let (cond, target) = (operands[0], operands[1]);
+            let mut succ = Vec::new();
+
+            let (may_jump, may_continue) = if cond.mode == Mode::Immediate {
+                let jumps = (cond.value != 0) == (opcode == Opcode::JumpIfTrue);
+                (jumps, !jumps)
+            } else {
+                (true, true)
+            };
+            if may_continue {
+                succ.push(next);
+            }
+            if may_jump && target.mode == Mode::Immediate && target.value >= 0 {
+                succ.push(target.value as usize);
+            }
+            succ
+        }
+        _ => vec![next],
+    }
+}
+
+// Addresses of all instructions reachable from address 0
+pub fn reachable(program: &[Code]) -> Vec<bool> {
+    let mut reached = vec![false; program.len()];
+    let mut stack = vec![0];
+
+    while let Some(address) = stack.pop() {
+        if address >= program.len() || reached[address] {
+            continue;
+        }
+        if let Some(item) = decode_at(program, address) {
+            reached[address] = true;
+            stack.extend(successors(&item));
+        }
+    }
+    reached
+}
+
+pub fn disassemble(program: &[Code], follow: bool) -> Vec<Item> {
+    let reached = if follow { Some(reachable(program)) } else { None };
+    let mut items = Vec::new();
+    let mut address = 0;
+
+    while address < program.len() {
+        #[allow(clippy::unnecessary_map_or)]
+        let is_code = reached.as_ref().map_or(true, |r| r[address]);
+        let item = if is_code { decode_at(program, address) } else { None };
+        let item = item.unwrap_or(Item::Data { address, value: program[address] });
+
+        address += item.size();
+        items.push(item);
+    }
+    items
+}
+
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    let follow = args.iter().any(|a| a == "--follow");
+    let filename = match args.iter().find(|a| !a.starts_with("--")) {
+        Some(filename) => filename,
+        None => return Err(PuzzleError::new("usage: intcode disasm <file> [--follow]")),
+    };
+
+    let program = load_code(filename)?;
+    for item in disassemble(&program, follow) {
+        println!("{}", item);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(program: &[Code], follow: bool) -> Vec<String> {
+        disassemble(program, follow).iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn test_operand_modes() {
+        let expected = vec![
+            "    0  add [9], #5, rb+3",
+            "    4  arb rb-2",
+            "    6  hlt",
+        ];
+        assert_eq!(expected, render(&[21001, 9, 5, 3, 209, -2, 99], false));
+    }
+
+    #[test]
+    fn test_linear_sweep_data() {
+        let expected = vec![
+            "    0  mul [3], [4], [5]",
+            "    4  data 42",
+            "    5  data 1",
+            "    6  data 0",
+        ];
+        assert_eq!(expected, render(&[2, 3, 4, 5, 42, 1, 0], false));
+    }
+
+    #[test]
+    fn test_follow_control_flow() {
+        // The jump skips over a data cell that happens to decode as `out`
+        let program = vec![1105, 1, 4, 4, 99, 1, 2, 3];
+        let expected = vec![
+            "    0  jnz #1, #4",
+            "    3  data 4",
+            "    4  hlt",
+            "    5  data 1",
+            "    6  data 2",
+            "    7  data 3",
+        ];
+        assert_eq!(expected, render(&program, true));
+
+        let linear = render(&program, false);
+        assert_eq!("    3  out [99]", linear[1]);
+    }
+
+    #[test]
+    fn test_truncated_instruction() {
+        let expected = vec!["    0  hlt", "    1  data 1", "    2  data 0"];
+        assert_eq!(expected, render(&[99, 1, 0], false));
+    }
+}
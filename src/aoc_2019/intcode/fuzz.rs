@@ -0,0 +1,276 @@
+use std::fmt::Write;
+
+use super::backend::Predecoded;
+use super::asm::assemble;
+use super::disasm::disassemble;
+use super::{Code, Guard, History, Instruction, IntCode, Mode, Opcode, State, VmError};
+use crate::PuzzleError;
+
+// Instructions executed by each run before giving up
+const STEP_LIMIT: u64 = 2000;
+
+// SplitMix64: small, seedable and good enough to generate programs
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform enough in 0..n for small n
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    pub fn range(&mut self, low: Code, high: Code) -> Code {
+        low + self.below((high - low) as u64) as Code
+    }
+}
+
+// A random program of about `size` cells. Most instructions are valid and
+// address memory near the program, but some cells are left as garbage.
+pub fn generate(rng: &mut Rng, size: usize) -> Vec<Code> {
+    let mut program = Vec::new();
+    let reach = size as Code + 8;
+
+    while program.len() < size {
+        if rng.below(20) == 0 {
+            program.push(rng.range(-100, 30000));
+            continue;
+        }
+        let opcode = Opcode::ALL[rng.below(Opcode::ALL.len() as u64) as usize];
+        let mut modes = [Mode::Position; 3];
+        for (index, mode) in modes.iter_mut().enumerate().take(opcode.arity()) {
+            *mode = match rng.below(3) {
+                0 => Mode::Position,
+                1 if opcode.write_param() != Some(index) => Mode::Immediate,
+                _ => Mode::Relative,
+            };
+        }
+        program.push(Instruction::new(opcode, modes).encode());
+
+        for (index, &mode) in modes.iter().enumerate().take(opcode.arity()) {
+            let value = match (opcode, index, mode) {
+                // Mostly jump to the start of the program, to loop a bit
+                (Opcode::JumpIfTrue, 1, Mode::Immediate) | (Opcode::JumpIfFalse, 1, Mode::Immediate) => {
+                    rng.range(0, reach)
+                }
+                (Opcode::AdjustBase, _, Mode::Immediate) => rng.range(-8, 9),
+                (_, _, Mode::Immediate) => rng.range(-5, 100),
+                (_, _, Mode::Relative) => rng.range(-4, reach),
+                _ => rng.range(0, reach),
+            };
+            program.push(value);
+        }
+    }
+    program
+}
+
+// How a run ended, with the budget errors of the different runners unified
+#[derive(Debug, Clone, PartialEq)]
+enum Outcome {
+    State(State),
+    Error(VmError),
+    OutOfSteps,
+}
+
+type Run = (Outcome, IntCode);
+
+fn limited<F>(vm: &mut IntCode, mut step: F) -> Outcome
+where
+    F: FnMut(&mut IntCode) -> Result<State, VmError>,
+{
+    for _ in 0..STEP_LIMIT {
+        match step(vm) {
+            Ok(State::Running) => (),
+            Ok(state) => return Outcome::State(state),
+            Err(err) => return Outcome::Error(err),
+        }
+    }
+    Outcome::OutOfSteps
+}
+
+fn runs(program: &[Code], input: &[Code]) -> Vec<(&'static str, Run)> {
+    let mut start = IntCode::new(program);
+    start.input.extend(input);
+    let mut runs = Vec::new();
+
+    let mut vm = start.clone();
+    runs.push(("interpreter", (limited(&mut vm, |vm| vm.step()), vm)));
+
+    let mut vm = start.clone();
+    let mut cache = Predecoded::new(program);
+    runs.push(("predecoded", (limited(&mut vm, |vm| cache.step(vm)), vm)));
+
+    let mut vm = start.clone();
+    let mut history = History::new();
+    runs.push(("history", (limited(&mut vm, |vm| history.step(vm)), vm)));
+
+    let mut vm = start;
+    let outcome = match Guard::new().budget(STEP_LIMIT).run(&mut vm) {
+        Ok(state) => Outcome::State(state),
+        Err(VmError::BudgetExceeded { .. }) => Outcome::OutOfSteps,
+        Err(err) => Outcome::Error(err),
+    };
+    runs.push(("guard", (outcome, vm)));
+    runs
+}
+
+// With loop detection, the guard may stop before running out of steps:
+// the machine must then really be stuck in a loop
+fn check_loop_detection(program: &[Code], input: &[Code], expected: &Outcome) -> Result<(), String> {
+    let mut vm = IntCode::new(program);
+    vm.input.extend(input);
+    match Guard::new().budget(STEP_LIMIT).detect_loops().run(&mut vm) {
+        Err(VmError::InfiniteLoop { .. }) if *expected == Outcome::OutOfSteps => Ok(()),
+        Err(VmError::InfiniteLoop { start, end }) => {
+            Err(format!("loop {}..{} reported, but the program ended with {:?}", start, end, expected))
+        }
+        Err(VmError::BudgetExceeded { .. }) if *expected == Outcome::OutOfSteps => Ok(()),
+        Ok(state) if *expected == Outcome::State(state) => Ok(()),
+        Err(err) if *expected == Outcome::Error(err) => Ok(()),
+        other => Err(format!("loop detection ended with {:?} instead of {:?}", other, expected)),
+    }
+}
+
+// Runs the program in every configuration, and describes the first
+// invariant that doesn't hold
+pub fn check(program: &[Code], input: &[Code]) -> Result<(), String> {
+    let runs = runs(program, input);
+    let (_, (outcome, expected)) = &runs[0];
+
+    for (name, (other_outcome, other)) in &runs[1..] {
+        if outcome != other_outcome {
+            return Err(format!("{} ended with {:?} instead of {:?}", name, other_outcome, outcome));
+        }
+        if expected.memory != other.memory || expected.output != other.output
+            || expected.pointer != other.pointer || expected.relative_base != other.relative_base
+        {
+            return Err(format!("{} ended in a different state", name));
+        }
+    }
+
+    check_loop_detection(program, input, outcome)?;
+
+    // Rewinding the whole run restores the initial memory
+    let mut vm = IntCode::new(program);
+    vm.input.extend(input);
+    let mut history = History::new();
+    limited(&mut vm, |vm| history.step(vm));
+    while history.step_back(&mut vm).is_some() {}
+    if vm.pointer != 0 || (0..program.len()).any(|a| vm.memory[a] != program[a]) {
+        return Err("rewinding didn't restore the initial state".to_string());
+    }
+
+    let restored = IntCode::from_snapshot(&expected.to_snapshot())
+        .map_err(|err| format!("snapshot failed to load: {}", err))?;
+    if restored.memory != expected.memory || restored.pointer != expected.pointer {
+        return Err("snapshot didn't round-trip".to_string());
+    }
+
+    let mut listing = String::new();
+    for item in disassemble(program, false) {
+        writeln!(listing, "{}", item).unwrap();
+    }
+    match assemble(&listing) {
+        Ok(code) if code == program => Ok(()),
+        Ok(_) => Err("disassembly reassembled to a different program".to_string()),
+        Err(err) => Err(format!("disassembly failed to assemble: {}", err)),
+    }
+}
+
+// Entry point for the `cargo fuzz` target: each pair of bytes is a cell
+// value, so that any input is a program (often an invalid one)
+pub fn fuzz_bytes(data: &[u8]) {
+    let program: Vec<Code> = data.chunks(2)
+        .map(|pair| {
+            let high = pair[0] as i8 as Code;
+            let low = pair.get(1).copied().unwrap_or(0) as Code;
+            high * 256 + low
+        })
+        .collect();
+    if let Err(err) = check(&program, &[1, 0, 5]) {
+        panic!("{} for program {:?}", err, program);
+    }
+}
+
+// Checks `count` generated programs, starting from the seed
+pub fn run_seeded(seed: u64, count: u64) -> Result<(), String> {
+    for seed in seed..seed + count {
+        let mut rng = Rng::new(seed);
+        let size = rng.range(1, 60) as usize;
+        let program = generate(&mut rng, size);
+        let input: Vec<Code> = (0..rng.below(4)).map(|_| rng.range(-10, 10)).collect();
+        check(&program, &input)
+            .map_err(|err| format!("seed {}: {} for program {:?}", seed, err, program))?;
+    }
+    Ok(())
+}
+
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    let seed = match args.first() {
+        Some(seed) => seed.parse()?,
+        None => 0,
+    };
+    let count = match args.get(1) {
+        Some(count) => count.parse()?,
+        None => 10000,
+    };
+    run_seeded(seed, count).map_err(|err| PuzzleError::new(&err))?;
+    println!("{} programs checked", count);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng_is_deterministic() {
+        let (mut a, mut b) = (Rng::new(42), Rng::new(42));
+        let program = generate(&mut a, 30);
+        assert_eq!(program, generate(&mut b, 30));
+        assert!(program.len() >= 30);
+    }
+
+    #[test]
+    fn test_seeded_programs() {
+        assert_eq!(Ok(()), run_seeded(0, 300));
+    }
+
+    #[test]
+    fn test_assembled_programs() {
+        let programs = [
+            "loop: add [n], #-1, [n]\njnz [n], #loop\nout [n]\nhlt\nn: data 50",
+            "in rb+5\narb #3\nmul rb+2, #-3, [100]\nout [100]\nhlt",
+            "add #0, #99, [next]\nnext: data 0",
+        ];
+        for source in programs.iter() {
+            assert_eq!(Ok(()), check(&assemble(source).unwrap(), &[7]));
+        }
+    }
+
+    #[test]
+    fn test_regressions() {
+        // Relative input address out of range while waiting for input
+        assert_eq!(Ok(()), check(&[203, -3, 99], &[]));
+        // Both a read and the write address out of range: the read fails first
+        assert_eq!(Ok(()), check(&[20208, -4, 3, -2], &[]));
+    }
+
+    #[test]
+    fn test_malformed_bytes() {
+        fuzz_bytes(&[]);
+        fuzz_bytes(&[0, 3, 255, 255, 0, 99]);
+        fuzz_bytes(&[0, 9, 128, 0, 0, 6, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use super::backend::Runner;
+use super::trace::NoTrace;
+use super::vm::Decoded;
+use super::{Code, IntCode, Opcode, State, VmError};
+
+// Runs a machine with an optional instruction budget and loop detection.
+//
+// The loop detector keeps a running hash of the memory, updated on each
+// write, and records the machine's state at every backward jump. Seeing
+// the same state twice without any I/O in between means the program can
+// only repeat itself forever; the loop is then replayed on a copy of the
+// machine to confirm it and find the addresses it covers.
+//
+// The budget covers every run of the guard, so that a machine resumed after
+// waiting for input can't exceed it either.
+#[derive(Debug, Clone, Default)]
+pub struct Guard {
+    budget: Option<u64>,
+    detect_loops: bool,
+    // Instructions executed so far, over all runs
+    steps: u64,
+}
+
+fn cell_hash(address: usize, value: Code) -> u64 {
+    if value == 0 {
+        return 0;
+    }
+    // SplitMix64 finalizer
+    let mut z = (address as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ value as u64;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn memory_hash(vm: &IntCode) -> u64 {
+    let dense = vm.memory.as_slice().iter().enumerate().map(|(a, &v)| cell_hash(a, v));
+    let sparse = vm.memory.sparse_cells().into_iter().map(|(a, v)| cell_hash(a, v));
+    dense.chain(sparse).fold(0, u64::wrapping_add)
+}
+
+// Executes one instruction, keeping the memory hash up to date. An invalid
+// write address makes the instruction fail without writing anything.
+fn hashed_step(vm: &mut IntCode, hash: &mut u64) -> Result<(State, Decoded), VmError> {
+    let decoded = vm.fetch()?;
+    let target = vm.write_address(&decoded).ok().flatten();
+    if let Some(address) = target {
+        *hash = hash.wrapping_sub(cell_hash(address, vm.memory[address]));
+    }
+    let state = vm.execute(&mut NoTrace, &decoded);
+    if let Some(address) = target {
+        *hash = hash.wrapping_add(cell_hash(address, vm.memory[address]));
+    }
+    Ok((state?, decoded))
+}
+
+fn is_backward_jump(decoded: &Decoded, from: usize, to: usize) -> bool {
+    let opcode = decoded.instruction.opcode;
+    (opcode == Opcode::JumpIfTrue || opcode == Opcode::JumpIfFalse) && to <= from
+}
+
+// Replays up to `max_steps` instructions from a state seen twice, and returns
+// the range of addresses executed until the exact same state comes back
+fn confirm_loop(vm: &IntCode, hash: u64, max_steps: u64) -> Option<(usize, usize)> {
+    let mut probe = vm.clone();
+    let mut probe_hash = hash;
+    let (mut start, mut end) = (vm.pointer, vm.pointer);
+
+    for _ in 0..max_steps {
+        let from = probe.pointer;
+        let (state, decoded) = hashed_step(&mut probe, &mut probe_hash).ok()?;
+        if state != State::Running {
+            return None;
+        }
+        start = start.min(from);
+        end = end.max(from + decoded.instruction.size() - 1);
+
+        if is_backward_jump(&decoded, from, probe.pointer)
+            && probe.pointer == vm.pointer
+            && probe.relative_base == vm.relative_base
+            && probe_hash == hash
+            && probe.memory == vm.memory
+        {
+            return Some((start, end));
+        }
+    }
+    None
+}
+
+impl Guard {
+    pub fn new() -> Guard {
+        Guard::default()
+    }
+
+    pub fn budget(mut self, steps: u64) -> Guard {
+        self.budget = Some(steps);
+        self
+    }
+
+    pub fn detect_loops(mut self) -> Guard {
+        self.detect_loops = true;
+        self
+    }
+
+    // Runs until the program halts or needs more input, like `IntCode::run`
+    pub fn run(&mut self, vm: &mut IntCode) -> Result<State, VmError> {
+        self.run_with(vm, &mut Runner::Interpreter)
+    }
+
+    // Same as `run` on the given backend. Loop detection always steps
+    // through the interpreter, which keeps the memory hash up to date.
+    pub fn run_with(&mut self, vm: &mut IntCode, runner: &mut Runner) -> Result<State, VmError> {
+        let mut hash = if self.detect_loops { memory_hash(vm) } else { 0 };
+        let mut seen: HashMap<(usize, Code, u64), u64> = HashMap::new();
+
+        loop {
+            if Some(self.steps) == self.budget {
+                return Err(VmError::BudgetExceeded { pointer: vm.pointer, budget: self.steps });
+            }
+            if !self.detect_loops {
+                match runner.step(vm)? {
+                    State::Running => (),
+                    state => return Ok(state),
+                }
+                self.steps += 1;
+                continue;
+            }
+
+            let from = vm.pointer;
+            let (state, decoded) = hashed_step(vm, &mut hash)?;
+            if state != State::Running {
+                return Ok(state);
+            }
+            self.steps += 1;
+
+            match decoded.instruction.opcode {
+                Opcode::Input | Opcode::Output => seen.clear(),
+                _ if is_backward_jump(&decoded, from, vm.pointer) => {
+                    let key = (vm.pointer, vm.relative_base, hash);
+                    if let Some(&first) = seen.get(&key) {
+                        if let Some((start, end)) = confirm_loop(vm, hash, self.steps - first) {
+                            return Err(VmError::InfiniteLoop { start, end });
+                        }
+                    }
+                    seen.insert(key, self.steps);
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+
+    fn machine(source: &str) -> IntCode {
+        IntCode::new(&assemble(source).unwrap())
+    }
+
+    const COUNTDOWN: &str = "
+        loop:   add [n], #-1, [n]
+                jnz [n], #loop
+                hlt
+        n:      data 1000
+    ";
+
+    #[test]
+    fn test_budget() {
+        let mut guard = Guard::new().budget(100);
+        let expected = VmError::BudgetExceeded { pointer: 0, budget: 100 };
+        assert_eq!(Err(expected), guard.run(&mut machine(COUNTDOWN)));
+
+        let mut guard = Guard::new().budget(2001);
+        assert_eq!(Ok(State::Halted), guard.run(&mut machine(COUNTDOWN)));
+    }
+
+    #[test]
+    fn test_finite_loop_not_reported() {
+        let mut guard = Guard::new().detect_loops();
+        assert_eq!(Ok(State::Halted), guard.run(&mut machine(COUNTDOWN)));
+    }
+
+    #[test]
+    fn test_tight_loop() {
+        let mut vm = machine("
+                    out #1
+            spin:   jnz #1, #spin
+        ");
+        let mut guard = Guard::new().detect_loops();
+        assert_eq!(Err(VmError::InfiniteLoop { start: 2, end: 4 }), guard.run(&mut vm));
+        assert_eq!(Some(1), vm.pop_output());
+    }
+
+    #[test]
+    fn test_loop_modifying_memory() {
+        // Toggles a cell forever: the state repeats every other iteration
+        let mut vm = machine("
+            loop:   eq [x], #0, [x]
+                    jz #0, #loop
+            x:      data 0
+        ");
+        let mut guard = Guard::new().detect_loops().budget(1000);
+        assert_eq!(Err(VmError::InfiniteLoop { start: 0, end: 6 }), guard.run(&mut vm));
+    }
+
+    #[test]
+    fn test_loop_with_output_not_reported() {
+        let mut vm = machine("loop: out #1\njnz #1, #loop\n");
+        let mut guard = Guard::new().detect_loops().budget(1000);
+        let expected = VmError::BudgetExceeded { pointer: 0, budget: 1000 };
+        assert_eq!(Err(expected), guard.run(&mut vm));
+    }
+
+    #[test]
+    fn test_errors_match_interpreter() {
+        // Invalid write address, then invalid read and write addresses
+        for program in &[vec![1101, 1, 1, -1, 99], vec![1, -5, 0, -1, 99]] {
+            let expected = IntCode::new(program).run();
+            assert!(expected.is_err());
+            let mut guard = Guard::new().detect_loops();
+            assert_eq!(expected, guard.run(&mut IntCode::new(program)));
+        }
+    }
+
+    #[test]
+    fn test_budget_across_resumes() {
+        let source = "
+            loop:   in [x]
+                    jnz [x], #loop
+                    hlt
+            x:      data 0
+        ";
+        let mut vm = machine(source);
+        let mut guard = Guard::new().budget(5);
+        vm.push_input(1);
+        assert_eq!(Ok(State::WaitingForInput), guard.run(&mut vm));
+        vm.push_input(1);
+        assert_eq!(Ok(State::WaitingForInput), guard.run(&mut vm));
+        vm.push_input(0);
+        let expected = VmError::BudgetExceeded { pointer: 2, budget: 5 };
+        assert_eq!(Err(expected), guard.run(&mut vm));
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+
+use super::{Code, IntCode, Opcode, State, VmError};
+
+// Everything an executed instruction changed, to be able to undo it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Change {
+    // Number of instructions executed before this one
+    pub step: u64,
+    pub pointer: usize,
+    pub relative_base: Code,
+    // Address written and the value it held before
+    pub write: Option<(usize, Code)>,
+    pub input: Option<Code>,
+    pub output: bool,
+    // Made by hand rather than by executing the instruction at `pointer`
+    pub edit: bool,
+}
+
+// Undo log for a machine, filled by stepping through it. The oldest
+// changes are dropped once the log reaches its limit.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    changes: VecDeque<Change>,
+    limit: Option<usize>,
+    steps: u64,
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    pub fn with_limit(limit: usize) -> History {
+        History { limit: Some(limit), ..History::default() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+
+    // Number of instructions executed, minus the ones undone
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    // Same as `IntCode::step`, logging the changes
+    pub fn step(&mut self, vm: &mut IntCode) -> Result<State, VmError> {
+        let decoded = vm.fetch()?;
+        let write = match vm.write_address(&decoded) {
+            Ok(target) => target.map(|address| (address, vm.memory[address])),
+            // The instruction fails: let the machine report the right error
+            Err(_) => return vm.step(),
+        };
+        let mut change = Change {
+            step: self.steps,
+            pointer: vm.pointer,
+            relative_base: vm.relative_base,
+            write,
+            input: None,
+            output: decoded.instruction.opcode == Opcode::Output,
+            edit: false,
+        };
+        if decoded.instruction.opcode == Opcode::Input {
+            change.input = vm.input.front().copied();
+        }
+
+        let state = vm.step()?;
+        if state == State::Running {
+            self.push(change);
+            self.steps += 1;
+        }
+        Ok(state)
+    }
+
+    // Logs a change about to be made by hand to the pointer, the relative
+    // base or the given memory cell, so that it can be undone like a step
+    pub fn edit(&mut self, vm: &IntCode, address: Option<usize>) {
+        self.push(Change {
+            step: self.steps,
+            pointer: vm.pointer,
+            relative_base: vm.relative_base,
+            write: address.map(|address| (address, vm.memory[address])),
+            input: None,
+            output: false,
+            edit: true,
+        });
+    }
+
+    fn push(&mut self, change: Change) {
+        if self.limit == Some(self.changes.len()) {
+            self.changes.pop_front();
+        }
+        if self.limit != Some(0) {
+            self.changes.push_back(change);
+        }
+    }
+
+    pub fn run(&mut self, vm: &mut IntCode) -> Result<State, VmError> {
+        loop {
+            match self.step(vm)? {
+                State::Running => continue,
+                state => return Ok(state),
+            }
+        }
+    }
+
+    // Undoes the last executed instruction or edit, if it is still in the
+    // log. Outputs already taken from the machine can't be put back.
+    pub fn step_back(&mut self, vm: &mut IntCode) -> Option<Change> {
+        let change = self.changes.pop_back()?;
+        if let Some((address, value)) = change.write {
+            vm.memory[address] = value;
+        }
+        if let Some(value) = change.input {
+            vm.input.push_front(value);
+        }
+        if change.output {
+            // Later outputs were undone first, so this one is the newest
+            vm.output.pop_back();
+        }
+        vm.pointer = change.pointer;
+        vm.relative_base = change.relative_base;
+        if !change.edit {
+            self.steps -= 1;
+        }
+        Some(change)
+    }
+
+    // The last logged instruction that wrote to the address
+    pub fn last_write(&self, address: usize) -> Option<&Change> {
+        self.changes.iter().rev().find(|c| matches!(c.write, Some((a, _)) if a == address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+
+    const ECHO_SUM: &str = "
+        loop:   in [x]
+                add [x], [sum], [sum]
+                out [sum]
+                jnz [x], #loop
+                hlt
+        x:      data 0
+        sum:    data 0
+    ";
+
+    fn machine(input: &[Code]) -> IntCode {
+        let mut vm = IntCode::new(&assemble(ECHO_SUM).unwrap());
+        vm.input.extend(input);
+        vm
+    }
+
+    #[test]
+    fn test_rewind_to_start() {
+        let mut vm = machine(&[3, 4, 0]);
+        let start = vm.clone();
+        let mut history = History::new();
+        assert_eq!(Ok(State::Halted), history.run(&mut vm));
+        assert_eq!(12, history.steps());
+        assert_eq!(vec![3, 7, 7], vm.output.iter().copied().collect::<Vec<_>>());
+
+        while history.step_back(&mut vm).is_some() {}
+        assert_eq!(0, history.steps());
+        assert_eq!(start.memory, vm.memory);
+        assert_eq!(start.input, vm.input);
+        assert!(vm.output.is_empty());
+        assert_eq!(0, vm.pointer);
+    }
+
+    #[test]
+    fn test_replay_after_rewind() {
+        let mut vm = machine(&[3, 4, 0]);
+        let mut history = History::new();
+        for _ in 0..6 {
+            history.step(&mut vm).unwrap();
+        }
+        let checkpoint = vm.clone();
+        history.run(&mut vm).unwrap();
+        for _ in 0..6 {
+            history.step_back(&mut vm);
+        }
+        assert_eq!(checkpoint.memory, vm.memory);
+        assert_eq!(checkpoint.pointer, vm.pointer);
+        assert_eq!(checkpoint.output, vm.output);
+
+        history.run(&mut vm).unwrap();
+        assert_eq!(vec![3, 7, 7], vm.output.iter().copied().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_taken_outputs_stay_taken() {
+        let mut vm = machine(&[3, 0]);
+        let mut history = History::new();
+        history.run(&mut vm).unwrap();
+        assert_eq!(Some(3), vm.pop_output());
+
+        while history.step_back(&mut vm).is_some() {}
+        assert!(vm.output.is_empty());
+        assert_eq!(vec![3, 0], vm.input.iter().copied().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_last_write() {
+        let mut vm = machine(&[3, 4, 0]);
+        let mut history = History::new();
+        history.run(&mut vm).unwrap();
+
+        let change = history.last_write(13).unwrap();
+        assert_eq!((2, 9), (change.pointer, change.step));
+        assert_eq!(Some((13, 7)), change.write);
+        assert_eq!(Some(0), history.last_write(12).unwrap().input);
+        assert_eq!(None, history.last_write(3));
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut vm = machine(&[3, 4, 0]);
+        let mut history = History::with_limit(4);
+        history.run(&mut vm).unwrap();
+        assert_eq!(4, history.len());
+        while history.step_back(&mut vm).is_some() {}
+        assert_eq!(8, history.steps());
+        assert_eq!(0, vm.pointer);
+    }
+
+    #[test]
+    fn test_undo_edit() {
+        let mut vm = machine(&[3, 0]);
+        let mut history = History::new();
+        history.step(&mut vm).unwrap();
+        history.edit(&vm, Some(13));
+        vm.memory[13] = 100;
+        history.edit(&vm, None);
+        vm.pointer = 9;
+
+        assert_eq!((Some((13, 0)), true), history.last_write(13).map(|c| (c.write, c.edit)).unwrap());
+        history.step_back(&mut vm);
+        assert_eq!((2, 100), (vm.pointer, vm.memory[13]));
+        history.step_back(&mut vm);
+        assert_eq!((2, 0, 1), (vm.pointer, vm.memory[13], history.steps()));
+        history.step_back(&mut vm);
+        assert_eq!((0, 0), (vm.pointer, history.steps()));
+    }
+}
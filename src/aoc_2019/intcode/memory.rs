@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::ops::{Index, IndexMut};
+
+use super::cell::Cell;
+use super::Code;
+
+// Writes below this address (or the program size, if larger) grow the dense
+// part of the memory, anything above goes to a sparse map.
+const DENSE_LIMIT: usize = 1 << 16;
+
+// IntCode memory: unbounded, with unwritten cells reading as 0
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Memory<C = Code> {
+    dense: Vec<C>,
+    sparse: HashMap<usize, C>,
+    // Value of unwritten cells, to return a reference to
+    zero: C,
+}
+
+impl<C: Cell> Memory<C> {
+    pub fn new(program: &[C]) -> Memory<C> {
+        Memory { dense: program.to_vec(), sparse: HashMap::new(), zero: C::default() }
+    }
+
+    pub fn get(&self, address: usize) -> C {
+        self[address].clone()
+    }
+
+    pub fn set(&mut self, address: usize, value: C) {
+        self[address] = value;
+    }
+
+    // The contiguous memory from address 0, including the program
+    pub fn as_slice(&self) -> &[C] {
+        &self.dense
+    }
+
+    // Written cells outside of `as_slice`, sorted by address
+    pub fn sparse_cells(&self) -> Vec<(usize, C)> {
+        let mut cells: Vec<(usize, C)> = self.sparse.iter().map(|(&a, v)| (a, v.clone())).collect();
+        cells.sort_unstable_by_key(|&(address, _)| address);
+        cells
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty() && self.sparse.is_empty()
+    }
+}
+
+impl<C: Cell> Index<usize> for Memory<C> {
+    type Output = C;
+
+    fn index(&self, address: usize) -> &C {
+        match self.dense.get(address) {
+            Some(value) => value,
+            None => self.sparse.get(&address).unwrap_or(&self.zero),
+        }
+    }
+}
+
+impl<C: Cell> IndexMut<usize> for Memory<C> {
+    fn index_mut(&mut self, address: usize) -> &mut C {
+        if address < self.dense.len() {
+            &mut self.dense[address]
+        } else if address < DENSE_LIMIT.max(self.dense.len()) {
+            self.dense.resize(address + 1, C::default());
+            &mut self.dense[address]
+        } else {
+            self.sparse.entry(address).or_default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grows_on_write() {
+        let mut memory: Memory = Memory::new(&[1, 2, 3]);
+        assert_eq!(0, memory[10]);
+        assert_eq!(3, memory.len());
+
+        memory[10] = 7;
+        assert_eq!(7, memory.get(10));
+        assert_eq!(11, memory.len());
+        assert_eq!(0, memory[5]);
+    }
+
+    #[test]
+    fn test_sparse_cells() {
+        let mut memory: Memory = Memory::new(&[99]);
+        memory.set(1 << 40, 5);
+        memory.set(DENSE_LIMIT, 6);
+
+        assert_eq!(1, memory.len());
+        assert_eq!(5, memory[1 << 40]);
+        assert_eq!(vec![(DENSE_LIMIT, 6), (1 << 40, 5)], memory.sparse_cells());
+    }
+}
@@ -0,0 +1,259 @@
+use std::fs;
+
+use crate::PuzzleError;
+
+pub mod ascii;
+pub mod asm;
+pub mod backend;
+pub mod cell;
+pub mod cfg;
+pub mod cli;
+pub mod debugger;
+pub mod decompile;
+pub mod disasm;
+pub mod fuzz;
+pub mod guard;
+pub mod history;
+pub mod memory;
+pub mod network;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
+pub mod vm;
+
+pub use backend::Backend;
+pub use cell::Cell;
+pub use guard::Guard;
+pub use history::History;
+pub use memory::Memory;
+pub use vm::{IntCode, State, VmError};
+
+pub type Code = i64;
+
+pub fn parse_code(contents: &str) -> Vec<Code> {
+    let mut code = Vec::new();
+    for line in contents.split(',') {
+        if let Ok(n) = line.trim().parse() {
+            code.push(n);
+        }
+    }
+    code
+}
+
+pub fn load_code(filename: &str) -> Result<Vec<Code>, PuzzleError> {
+    let contents = fs::read_to_string(filename)?;
+    Ok(parse_code(&contents))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode { Position, Immediate, Relative }
+
+impl Mode {
+    fn from_digit(digit: Code) -> Option<Mode> {
+        match digit {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+
+    fn digit(&self) -> Code {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Add,
+    Mul,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustBase,
+    Halt,
+}
+
+impl Opcode {
+    pub const ALL: [Opcode; 10] = [
+        Opcode::Add,
+        Opcode::Mul,
+        Opcode::Input,
+        Opcode::Output,
+        Opcode::JumpIfTrue,
+        Opcode::JumpIfFalse,
+        Opcode::LessThan,
+        Opcode::Equals,
+        Opcode::AdjustBase,
+        Opcode::Halt,
+    ];
+
+    pub fn from_code(code: Code) -> Option<Opcode> {
+        Opcode::ALL.iter().find(|op| op.code() == code).copied()
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+        Opcode::ALL.iter().find(|op| op.mnemonic() == mnemonic).copied()
+    }
+
+    pub fn code(&self) -> Code {
+        match self {
+            Opcode::Add => 1,
+            Opcode::Mul => 2,
+            Opcode::Input => 3,
+            Opcode::Output => 4,
+            Opcode::JumpIfTrue => 5,
+            Opcode::JumpIfFalse => 6,
+            Opcode::LessThan => 7,
+            Opcode::Equals => 8,
+            Opcode::AdjustBase => 9,
+            Opcode::Halt => 99,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Opcode::Add => "add",
+            Opcode::Mul => "mul",
+            Opcode::Input => "in",
+            Opcode::Output => "out",
+            Opcode::JumpIfTrue => "jnz",
+            Opcode::JumpIfFalse => "jz",
+            Opcode::LessThan => "lt",
+            Opcode::Equals => "eq",
+            Opcode::AdjustBase => "arb",
+            Opcode::Halt => "hlt",
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals => 3,
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => 2,
+            Opcode::Input | Opcode::Output | Opcode::AdjustBase => 1,
+            Opcode::Halt => 0,
+        }
+    }
+
+    // Index of the parameter the instruction writes to, if any
+    pub fn write_param(&self) -> Option<usize> {
+        match self {
+            Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals => Some(2),
+            Opcode::Input => Some(0),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub modes: [Mode; 3],
+}
+
+impl Instruction {
+    pub fn new(opcode: Opcode, modes: [Mode; 3]) -> Instruction {
+        Instruction { opcode, modes }
+    }
+
+    pub fn decode(code: Code) -> Option<Instruction> {
+        if code < 0 {
+            return None;
+        }
+        let opcode = Opcode::from_code(code % 100)?;
+
+        let mut modes = [Mode::Position; 3];
+        let mut digits = code / 100;
+        for (i, mode) in modes.iter_mut().enumerate() {
+            *mode = Mode::from_digit(digits % 10)?;
+            // Unused parameters must keep the default mode
+            if i >= opcode.arity() && *mode != Mode::Position {
+                return None;
+            }
+            digits /= 10;
+        }
+        if digits != 0 {
+            return None;
+        }
+
+        if let Some(param) = opcode.write_param() {
+            if modes[param] == Mode::Immediate {
+                return None;
+            }
+        }
+        Some(Instruction { opcode, modes })
+    }
+
+    pub fn encode(&self) -> Code {
+        let mut code = 0;
+        for mode in self.modes.iter().rev() {
+            code = code * 10 + mode.digit();
+        }
+        code * 100 + self.opcode.code()
+    }
+
+    pub fn size(&self) -> usize {
+        self.opcode.arity() + 1
+    }
+}
+
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    match args.first().map(|s| s.as_str()) {
+        Some("ascii") => ascii::command(&args[1..]),
+        Some("asm") => asm::command(&args[1..]),
+        Some("bench") => backend::command(&args[1..]),
+        Some("cfg") => cfg::command(&args[1..]),
+        Some("debug") => debugger::command(&args[1..]),
+        Some("decompile") => decompile::command(&args[1..]),
+        Some("disasm") => disasm::command(&args[1..]),
+        Some("fuzz") => fuzz::command(&args[1..]),
+        Some("run") => cli::command(&args[1..]),
+        Some("symbolic") => symbolic::command(&args[1..]),
+        Some("trace") => trace::command(&args[1..]),
+        Some("widths") => cell::command(&args[1..]),
+        Some(other) => Err(PuzzleError::new(&format!("unknown intcode command: {}", other))),
+        None => Err(PuzzleError::new("missing intcode command")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_code() {
+        assert_eq!(vec![1, 0, 0, 3, 99], parse_code("1,0,0,3,99\n"));
+        assert_eq!(vec![-1, 5], parse_code(" -1 , 5 "));
+    }
+
+    #[test]
+    fn test_decode() {
+        let expected = Instruction::new(
+            Opcode::Mul,
+            [Mode::Position, Mode::Immediate, Mode::Position],
+        );
+        assert_eq!(Some(expected), Instruction::decode(1002));
+        assert_eq!(1002, expected.encode());
+
+        let relative = Instruction::decode(203).unwrap();
+        assert_eq!(Opcode::Input, relative.opcode);
+        assert_eq!(Mode::Relative, relative.modes[0]);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!(None, Instruction::decode(0));
+        assert_eq!(None, Instruction::decode(-1));
+        assert_eq!(None, Instruction::decode(10001));
+        assert_eq!(None, Instruction::decode(399));
+        assert_eq!(None, Instruction::decode(11199));
+        assert_eq!(None, Instruction::decode(301));
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+
+use super::{Code, IntCode, State, VmError};
+
+// Packet payload: the X and Y values following the destination address
+pub type Packet = (Code, Code);
+
+// What the special address handler wants the network to do next
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Continue,
+    Send(usize, Packet),
+    Stop(Code),
+}
+
+// Receives the packets sent to the special address, and gets a chance to
+// act whenever the whole network is idle
+pub trait Handler {
+    fn receive(&mut self, _packet: Packet) -> Action {
+        Action::Continue
+    }
+
+    fn idle(&mut self) -> Action {
+        Action::Continue
+    }
+}
+
+// Stops with the Y value of the first packet it receives
+#[derive(Debug, Clone, Default)]
+pub struct FirstPacket;
+
+impl Handler for FirstPacket {
+    fn receive(&mut self, (_, y): Packet) -> Action {
+        Action::Stop(y)
+    }
+}
+
+// Keeps the last packet it received, and sends it to address 0 whenever
+// the network is idle. Stops when it sends the same Y value twice in a row.
+#[derive(Debug, Clone, Default)]
+pub struct Nat {
+    pub last: Option<Packet>,
+    pub last_sent_y: Option<Code>,
+}
+
+impl Handler for Nat {
+    fn receive(&mut self, packet: Packet) -> Action {
+        self.last = Some(packet);
+        Action::Continue
+    }
+
+    fn idle(&mut self) -> Action {
+        match self.last {
+            Some((_, y)) if self.last_sent_y == Some(y) => Action::Stop(y),
+            Some(packet) => {
+                self.last_sent_y = Some(packet.1);
+                Action::Send(0, packet)
+            }
+            None => Action::Continue,
+        }
+    }
+}
+
+// Runs machines in round-robin order, each until it needs input, so that
+// results don't depend on anything but the program
+pub struct Network<H: Handler> {
+    pub machines: Vec<IntCode>,
+    pub handler: H,
+    handler_address: usize,
+    queues: Vec<VecDeque<Packet>>,
+    halted: Vec<bool>,
+    pub rounds: u64,
+    pub dropped: u64,
+}
+
+impl<H: Handler> Network<H> {
+    // Boots `size` machines, each receiving its address as first input
+    pub fn new(program: &[Code], size: usize, handler_address: usize, handler: H) -> Network<H> {
+        let machines = (0..size)
+            .map(|address| {
+                let mut vm = IntCode::new(program);
+                vm.push_input(address as Code);
+                vm
+            })
+            .collect();
+        Network {
+            machines,
+            handler,
+            handler_address,
+            queues: vec![VecDeque::new(); size],
+            halted: vec![false; size],
+            rounds: 0,
+            dropped: 0,
+        }
+    }
+
+    // Packets to addresses without a machine or handler are dropped
+    fn route(&mut self, address: Code, packet: Packet) -> Action {
+        if address >= 0 && address as usize == self.handler_address {
+            return self.handler.receive(packet);
+        }
+        match self.queues.get_mut(address as usize).filter(|_| address >= 0) {
+            Some(queue) => queue.push_back(packet),
+            None => self.dropped += 1,
+        }
+        Action::Continue
+    }
+
+    fn apply(&mut self, action: Action) -> Option<Code> {
+        match action {
+            Action::Continue => None,
+            Action::Send(address, packet) => {
+                let next = self.route(address as Code, packet);
+                self.apply(next)
+            }
+            Action::Stop(value) => Some(value),
+        }
+    }
+
+    // Gives every machine one chance to run. Returns whether any packet
+    // was received or sent, and the value to stop with if any.
+    pub fn round(&mut self) -> Result<(bool, Option<Code>), VmError> {
+        let mut active = false;
+        self.rounds += 1;
+
+        for index in 0..self.machines.len() {
+            if self.halted[index] {
+                continue;
+            }
+            let vm = &mut self.machines[index];
+            match self.queues[index].pop_front() {
+                Some((x, y)) => {
+                    vm.push_input(x);
+                    vm.push_input(y);
+                    active = true;
+                }
+                None => vm.push_input(-1),
+            }
+            self.halted[index] = vm.run()? == State::Halted;
+
+            while self.machines[index].output.len() >= 3 {
+                let output = &mut self.machines[index].output;
+                let packet: Vec<Code> = output.drain(..3).collect();
+                active = true;
+                let action = self.route(packet[0], (packet[1], packet[2]));
+                if let Some(value) = self.apply(action) {
+                    return Ok((true, Some(value)));
+                }
+            }
+        }
+        Ok((active, None))
+    }
+
+    // Runs until the handler stops the network. Returns None if the network
+    // is idle and the handler has nothing to send.
+    pub fn run(&mut self) -> Result<Option<Code>, VmError> {
+        loop {
+            let (active, stop) = self.round()?;
+            if stop.is_some() {
+                return Ok(stop);
+            }
+            if !active {
+                match self.handler.idle() {
+                    Action::Continue => return Ok(None),
+                    action => {
+                        if let Some(value) = self.apply(action) {
+                            return Ok(Some(value));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+
+    // Machine 0 sends a packet along the chain of machines, and the last
+    // one sends it to address 255. Each machine adds 1 to Y, up to 10.
+    const CHAIN: &str = "
+                in [addr]
+                add [addr], #1, [dest]
+                eq [dest], [size], [t]
+                jz [t], #boot
+                add #255, #0, [dest]
+        boot:   jnz [addr], #loop
+                out [dest]
+                out #0
+                out #0
+        loop:   in [x]
+                eq [x], #-1, [t]
+                jnz [t], #loop
+                in [y]
+                lt [y], #10, [t]
+                add [y], [t], [y]
+                out [dest]
+                out [x]
+                out [y]
+                jz #0, #loop
+        size:   data 4
+        addr:   data 0
+        dest:   data 0
+        x:      data 0
+        y:      data 0
+        t:      data 0
+    ";
+
+    fn program() -> Vec<Code> {
+        assemble(CHAIN).unwrap()
+    }
+
+    #[test]
+    fn test_first_packet() {
+        let mut network = Network::new(&program(), 4, 255, FirstPacket);
+        assert_eq!(Ok(Some(3)), network.run());
+    }
+
+    #[test]
+    fn test_nat() {
+        let mut network = Network::new(&program(), 4, 255, Nat::default());
+        assert_eq!(Ok(Some(10)), network.run());
+        assert_eq!(Some((0, 10)), network.handler.last);
+
+        // Same program, same schedule
+        let mut again = Network::new(&program(), 4, 255, Nat::default());
+        again.run().unwrap();
+        assert_eq!(network.rounds, again.rounds);
+    }
+
+    #[test]
+    fn test_idle_without_handler_action() {
+        // Nothing reaches the handler: the chain is longer than the network
+        let mut network = Network::new(&program(), 3, 255, Nat::default());
+        assert_eq!(Ok(None), network.run());
+        assert_eq!(1, network.dropped);
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::VecDeque;
+use std::fs;
+
+use super::{Code, IntCode, Memory};
+use crate::PuzzleError;
+
+// Snapshots are plain text, one field per line:
+//
+//     intcode-snapshot 1
+//     pointer 4
+//     relative_base 0
+//     input 5,6
+//     output
+//     memory 3,9,4,9,99,0,0,0,0,0
+//     sparse 100000=1,250000=-3
+//
+// The `sparse` line lists written cells far outside of the program, and is
+// omitted when there are none.
+// `IntCode` is `Clone`, so in-memory branching doesn't need any of this.
+
+const HEADER: &str = "intcode-snapshot 1";
+
+fn list_field<'a, I: Iterator<Item = &'a Code>>(field: &str, values: I) -> String {
+    let values: Vec<String> = values.map(|v| v.to_string()).collect();
+    if values.is_empty() {
+        field.to_string()
+    } else {
+        format!("{} {}", field, values.join(","))
+    }
+}
+
+fn parse_list(field: &str, text: &str) -> Result<Vec<Code>, PuzzleError> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    text.split(',')
+        .map(|v| v.trim().parse().map_err(|_| {
+            PuzzleError::new(&format!("invalid value '{}' in snapshot {}", v.trim(), field))
+        }))
+        .collect()
+}
+
+fn parse_sparse(text: &str) -> Result<Vec<(usize, Code)>, PuzzleError> {
+    let mut cells = Vec::new();
+    for cell in text.split(',').map(|c| c.trim()) {
+        let parsed = match cell.find('=') {
+            Some(split) => (cell[..split].parse(), cell[split + 1..].parse()),
+            None => return Err(PuzzleError::new(&format!("invalid sparse cell '{}'", cell))),
+        };
+        match parsed {
+            (Ok(address), Ok(value)) => cells.push((address, value)),
+            _ => return Err(PuzzleError::new(&format!("invalid sparse cell '{}'", cell))),
+        }
+    }
+    Ok(cells)
+}
+
+fn parse_number<T: std::str::FromStr>(field: &str, text: &str) -> Result<T, PuzzleError> {
+    text.parse().map_err(|_| PuzzleError::new(&format!("invalid snapshot {} '{}'", field, text)))
+}
+
+impl IntCode {
+    pub fn to_snapshot(&self) -> String {
+        let mut text = format!(
+            "{}\npointer {}\nrelative_base {}\n{}\n{}\n{}\n",
+            HEADER,
+            self.pointer,
+            self.relative_base,
+            list_field("input", self.input.iter()),
+            list_field("output", self.output.iter()),
+            list_field("memory", self.memory.as_slice().iter()),
+        );
+
+        let sparse: Vec<String> = self.memory.sparse_cells().iter()
+            .map(|(address, value)| format!("{}={}", address, value))
+            .collect();
+        if !sparse.is_empty() {
+            text += &format!("sparse {}\n", sparse.join(","));
+        }
+        text
+    }
+
+    pub fn from_snapshot(text: &str) -> Result<IntCode, PuzzleError> {
+        let mut lines = text.lines();
+        if lines.next().map(|l| l.trim()) != Some(HEADER) {
+            return Err(PuzzleError::new("not an IntCode snapshot"));
+        }
+
+        let mut computer = IntCode::new(&[]);
+        let mut sparse = Vec::new();
+        let mut has_memory = false;
+        for line in lines.map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let (field, value) = match line.find(' ') {
+                Some(split) => (&line[..split], line[split..].trim()),
+                None => (line, ""),
+            };
+            match field {
+                "pointer" => computer.pointer = parse_number(field, value)?,
+                "relative_base" => computer.relative_base = parse_number(field, value)?,
+                "input" => computer.input = VecDeque::from(parse_list(field, value)?),
+                "output" => computer.output = VecDeque::from(parse_list(field, value)?),
+                "memory" => {
+                    computer.memory = Memory::new(&parse_list(field, value)?);
+                    has_memory = true;
+                }
+                "sparse" => sparse = parse_sparse(value)?,
+                _ => return Err(PuzzleError::new(&format!("unknown snapshot field '{}'", field))),
+            }
+        }
+
+        if !has_memory {
+            return Err(PuzzleError::new("snapshot has no memory"));
+        }
+        for (address, value) in sparse {
+            computer.memory.set(address, value);
+        }
+        Ok(computer)
+    }
+
+    pub fn save(&self, filename: &str) -> Result<(), PuzzleError> {
+        fs::write(filename, self.to_snapshot())?;
+        Ok(())
+    }
+
+    pub fn load(filename: &str) -> Result<IntCode, PuzzleError> {
+        IntCode::from_snapshot(&fs::read_to_string(filename)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::State;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut computer = IntCode::new(&[3, 9, 4, 9, 109, 3, 3, 9, 99, 0]);
+        computer.input.extend(&[5, 6]);
+        for _ in 0..3 {
+            computer.step().unwrap();
+        }
+
+        let text = computer.to_snapshot();
+        assert_eq!("\
+intcode-snapshot 1
+pointer 6
+relative_base 3
+input 6
+output 5
+memory 3,9,4,9,109,3,3,9,99,5
+", text);
+
+        let mut restored = IntCode::from_snapshot(&text).unwrap();
+        assert_eq!(Ok(State::Halted), restored.run());
+        assert_eq!(6, restored.memory[9]);
+        assert_eq!(Some(5), restored.pop_output());
+    }
+
+    #[test]
+    fn test_snapshot_sparse_memory() {
+        let mut computer = IntCode::new(&[99]);
+        computer.memory.set(1 << 40, -3);
+
+        let text = computer.to_snapshot();
+        assert!(text.ends_with("memory 99\nsparse 1099511627776=-3\n"));
+        assert_eq!(computer.memory, IntCode::from_snapshot(&text).unwrap().memory);
+    }
+
+    #[test]
+    fn test_snapshot_errors() {
+        assert!(IntCode::from_snapshot("1,2,3").is_err());
+        assert!(IntCode::from_snapshot("intcode-snapshot 1\npointer 0\n").is_err());
+
+        let err = IntCode::from_snapshot("intcode-snapshot 1\nmemory 1,x\n").unwrap_err();
+        assert_eq!("invalid value 'x' in snapshot memory", err.to_string());
+    }
+}
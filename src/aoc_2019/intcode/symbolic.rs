@@ -0,0 +1,426 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+
+use super::{load_code, Code, Instruction, Mode, Opcode};
+use crate::PuzzleError;
+
+const STEP_LIMIT: usize = 1_000_000;
+
+// Polynomial over the machine's variables, mapping each monomial (the
+// exponent of every variable, without trailing zeros) to its coefficient.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    terms: BTreeMap<Vec<u32>, Code>,
+}
+
+impl Expr {
+    pub fn constant(value: Code) -> Expr {
+        let mut terms = BTreeMap::new();
+        if value != 0 {
+            terms.insert(vec![], value);
+        }
+        Expr { terms }
+    }
+
+    pub fn variable(index: usize) -> Expr {
+        let mut monomial = vec![0; index + 1];
+        monomial[index] = 1;
+        let mut terms = BTreeMap::new();
+        terms.insert(monomial, 1);
+        Expr { terms }
+    }
+
+    pub fn as_constant(&self) -> Option<Code> {
+        match self.terms.len() {
+            0 => Some(0),
+            1 => self.terms.get(&vec![]).copied(),
+            _ => None,
+        }
+    }
+
+    pub fn degree(&self) -> u32 {
+        self.terms.keys().map(|m| m.iter().sum()).max().unwrap_or(0)
+    }
+
+    // Constant term and the coefficient of each of the first `count` variables
+    pub fn as_linear(&self, count: usize) -> Option<(Code, Vec<Code>)> {
+        if self.degree() > 1 {
+            return None;
+        }
+        let mut coefficients = vec![0; count];
+        let mut constant = 0;
+        for (monomial, &coefficient) in &self.terms {
+            match monomial.len() {
+                0 => constant = coefficient,
+                n if n <= count => coefficients[n - 1] = coefficient,
+                _ => return None,
+            }
+        }
+        Some((constant, coefficients))
+    }
+
+    fn insert(terms: &mut BTreeMap<Vec<u32>, Code>, monomial: Vec<u32>, value: Code) -> Option<()> {
+        let sum = terms.get(&monomial).unwrap_or(&0).checked_add(value)?;
+        if sum == 0 {
+            terms.remove(&monomial);
+        } else {
+            terms.insert(monomial, sum);
+        }
+        Some(())
+    }
+
+    pub fn checked_add(&self, other: &Expr) -> Option<Expr> {
+        let mut terms = self.terms.clone();
+        for (monomial, &value) in &other.terms {
+            Expr::insert(&mut terms, monomial.clone(), value)?;
+        }
+        Some(Expr { terms })
+    }
+
+    pub fn checked_mul(&self, other: &Expr) -> Option<Expr> {
+        let mut terms = BTreeMap::new();
+        for (m_a, &a) in &self.terms {
+            for (m_b, &b) in &other.terms {
+                let mut monomial = vec![0; m_a.len().max(m_b.len())];
+                for (i, e) in m_a.iter().enumerate() {
+                    monomial[i] += e;
+                }
+                for (i, e) in m_b.iter().enumerate() {
+                    monomial[i] += e;
+                }
+                Expr::insert(&mut terms, monomial, a.checked_mul(b)?)?;
+            }
+        }
+        Some(Expr { terms })
+    }
+
+    pub fn render(&self, names: &[String]) -> String {
+        if self.terms.is_empty() {
+            return "0".to_string();
+        }
+        let mut parts = Vec::new();
+        for (monomial, coefficient) in self.terms.iter().rev() {
+            let mut factors = Vec::new();
+            for (i, &exponent) in monomial.iter().enumerate() {
+                let name = names.get(i).cloned().unwrap_or_else(|| format!("v{}", i));
+                match exponent {
+                    0 => (),
+                    1 => factors.push(name),
+                    _ => factors.push(format!("{}^{}", name, exponent)),
+                }
+            }
+            let part = match (coefficient, factors.is_empty()) {
+                (_, true) => coefficient.to_string(),
+                (1, false) => factors.join("*"),
+                _ => format!("{}*{}", coefficient, factors.join("*")),
+            };
+            parts.push(part);
+        }
+        parts.join(" + ").replace("+ -", "- ")
+    }
+}
+
+// A cell is either a known expression, or a value that couldn't be tracked,
+// like a read from an address depending on the variables.
+pub type Value = Option<Expr>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolicError {
+    pub pointer: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at address {}", self.reason, self.pointer)
+    }
+}
+
+impl Error for SymbolicError {}
+
+// IntCode machine where memory cells hold expressions. Inputs become new
+// variables; anything that would need the actual value of a variable to
+// continue (a branch, a write address, the instruction itself) is an error.
+pub struct SymbolicMachine {
+    memory: Vec<Value>,
+    // Cells written beyond the program, which can be anywhere
+    beyond: HashMap<usize, Value>,
+    pointer: usize,
+    relative_base: Code,
+    pub names: Vec<String>,
+    pub output: Vec<Value>,
+}
+
+impl SymbolicMachine {
+    pub fn new(program: &[Code]) -> SymbolicMachine {
+        SymbolicMachine {
+            memory: program.iter().map(|&c| Some(Expr::constant(c))).collect(),
+            beyond: HashMap::new(),
+            pointer: 0,
+            relative_base: 0,
+            names: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    // Adds a variable, stored at the given address
+    pub fn bind(&mut self, address: usize, name: &str) -> Expr {
+        let var = self.new_variable(name);
+        self.store(address, Some(var.clone()));
+        var
+    }
+
+    fn new_variable(&mut self, name: &str) -> Expr {
+        self.names.push(name.to_string());
+        Expr::variable(self.names.len() - 1)
+    }
+
+    pub fn load(&self, address: usize) -> Value {
+        match self.memory.get(address).or_else(|| self.beyond.get(&address)) {
+            Some(value) => value.clone(),
+            None => Some(Expr::constant(0)),
+        }
+    }
+
+    fn store(&mut self, address: usize, value: Value) {
+        match self.memory.get_mut(address) {
+            Some(cell) => *cell = value,
+            None => {
+                self.beyond.insert(address, value);
+            }
+        }
+    }
+
+    fn error(&self, reason: &'static str) -> SymbolicError {
+        SymbolicError { pointer: self.pointer, reason }
+    }
+
+    fn concrete(&self, address: usize, reason: &'static str) -> Result<Code, SymbolicError> {
+        self.load(address).and_then(|e| e.as_constant()).ok_or_else(|| self.error(reason))
+    }
+
+    // Address of a parameter, or None if it depends on the variables
+    fn param_address(&self, mode: Mode, index: usize) -> Result<Option<usize>, SymbolicError> {
+        let raw_address = self.pointer + index + 1;
+        let raw = match self.load(raw_address).and_then(|e| e.as_constant()) {
+            Some(raw) => raw,
+            None if mode == Mode::Immediate => return Ok(Some(raw_address)),
+            None => return Ok(None),
+        };
+        let address = match mode {
+            Mode::Position => raw,
+            Mode::Relative => self.relative_base.checked_add(raw).ok_or_else(|| self.error("overflow"))?,
+            Mode::Immediate => return Ok(Some(raw_address)),
+        };
+        if address < 0 {
+            return Err(self.error("invalid address"));
+        }
+        Ok(Some(address as usize))
+    }
+
+    fn read(&self, instr: &Instruction, index: usize) -> Result<Value, SymbolicError> {
+        Ok(match self.param_address(instr.modes[index], index)? {
+            Some(address) => self.load(address),
+            None => None,
+        })
+    }
+
+    fn write(&mut self, instr: &Instruction, index: usize, value: Value) -> Result<(), SymbolicError> {
+        match self.param_address(instr.modes[index], index)? {
+            Some(address) => {
+                self.store(address, value);
+                Ok(())
+            }
+            None => Err(self.error("write to a symbolic address")),
+        }
+    }
+
+    fn condition(&self, instr: &Instruction, index: usize) -> Result<Code, SymbolicError> {
+        self.read(instr, index)?
+            .and_then(|e| e.as_constant())
+            .ok_or_else(|| self.error("branch on a symbolic value"))
+    }
+
+    // Returns true when the machine halts
+    pub fn step(&mut self) -> Result<bool, SymbolicError> {
+        let code = self.concrete(self.pointer, "symbolic instruction")?;
+        let instr = Instruction::decode(code).ok_or_else(|| self.error("invalid instruction"))?;
+        let mut next = self.pointer + instr.size();
+
+        match instr.opcode {
+            Opcode::Add | Opcode::Mul => {
+                let (a, b) = (self.read(&instr, 0)?, self.read(&instr, 1)?);
+                let value = match (a, b) {
+                    (Some(a), Some(b)) if instr.opcode == Opcode::Add => a.checked_add(&b),
+                    (Some(a), Some(b)) => a.checked_mul(&b),
+                    _ => None,
+                };
+                self.write(&instr, 2, value)?;
+            }
+            Opcode::LessThan | Opcode::Equals => {
+                let (a, b) = (self.read(&instr, 0)?, self.read(&instr, 1)?);
+                let value = match (a, b) {
+                    (Some(a), Some(b)) => match (a.as_constant(), b.as_constant()) {
+                        (Some(a), Some(b)) if instr.opcode == Opcode::LessThan => Some(a < b),
+                        (Some(a), Some(b)) => Some(a == b),
+                        _ if a == b => Some(instr.opcode == Opcode::Equals),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                self.write(&instr, 2, value.map(|v| Expr::constant(v as Code)))?;
+            }
+            Opcode::Input => {
+                let name = format!("in{}", self.names.len());
+                let var = self.new_variable(&name);
+                self.write(&instr, 0, Some(var))?;
+            }
+            Opcode::Output => {
+                let value = self.read(&instr, 0)?;
+                self.output.push(value);
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let cond = self.condition(&instr, 0)? != 0;
+                if cond == (instr.opcode == Opcode::JumpIfTrue) {
+                    let target = self.condition(&instr, 1)?;
+                    if target < 0 {
+                        return Err(self.error("invalid address"));
+                    }
+                    next = target as usize;
+                }
+            }
+            Opcode::AdjustBase => {
+                let offset = self.condition(&instr, 0)?;
+                self.relative_base = self.relative_base.checked_add(offset)
+                    .ok_or_else(|| self.error("overflow"))?;
+            }
+            Opcode::Halt => return Ok(true),
+        }
+
+        self.pointer = next;
+        Ok(false)
+    }
+
+    pub fn run(&mut self) -> Result<(), SymbolicError> {
+        for _ in 0..STEP_LIMIT {
+            if self.step()? {
+                return Ok(());
+            }
+        }
+        Err(self.error("step limit reached"))
+    }
+}
+
+fn render_value(value: &Value, names: &[String]) -> String {
+    match value {
+        Some(expr) => expr.render(names),
+        None => "?".to_string(),
+    }
+}
+
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    let filename = match args.first() {
+        Some(filename) => filename,
+        None => return Err(PuzzleError::new("usage: intcode symbolic <file> [addr=name]...")),
+    };
+    let mut machine = SymbolicMachine::new(&load_code(filename)?);
+    for binding in &args[1..] {
+        match binding.find('=') {
+            Some(split) => {
+                machine.bind(binding[..split].parse()?, &binding[split + 1..]);
+            }
+            None => return Err(PuzzleError::new(&format!("invalid binding '{}'", binding))),
+        }
+    }
+
+    if let Err(err) = machine.run() {
+        println!("Stopped: {}", err);
+    }
+    println!("[0] = {}", render_value(&machine.load(0), &machine.names));
+    for value in &machine.output {
+        println!("out {}", render_value(value, &machine.names));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+
+    #[test]
+    fn test_expr_arithmetic() {
+        let (x, y) = (Expr::variable(0), Expr::variable(1));
+        let sum = x.checked_add(&Expr::constant(3)).unwrap();
+        let product = sum.checked_mul(&y).unwrap().checked_mul(&Expr::constant(2)).unwrap();
+
+        let names = vec!["x".to_string(), "y".to_string()];
+        assert_eq!("x + 3", sum.render(&names));
+        assert_eq!("2*x*y + 6*y", product.render(&names));
+        assert_eq!(2, product.degree());
+        assert_eq!(None, product.as_linear(2));
+        assert_eq!(Some((3, vec![1, 0])), sum.as_linear(2));
+        assert_eq!(Some(0), x.checked_add(&Expr::constant(-1)).unwrap()
+            .checked_mul(&Expr::constant(0)).unwrap().as_constant());
+    }
+
+    #[test]
+    fn test_linear_program() {
+        // mem[0] = 5 * a + b - 7
+        let program = assemble("
+            mul [a], #5, [0]
+            add [0], [b], [0]
+            add [0], #-7, [0]
+            hlt
+            a: data 0
+            b: data 0
+        ").unwrap();
+        let mut machine = SymbolicMachine::new(&program);
+        machine.bind(13, "a");
+        machine.bind(14, "b");
+        machine.run().unwrap();
+
+        let result = machine.load(0).unwrap();
+        assert_eq!(Some((-7, vec![5, 1])), result.as_linear(2));
+        assert_eq!("5*a + b - 7", result.render(&machine.names));
+    }
+
+    #[test]
+    fn test_symbolic_reads_and_inputs() {
+        // Reading through a symbolic pointer gives an unknown value,
+        // inputs become new variables
+        let program = assemble("
+            add [p], [p], [x]
+            in [y]
+            mul [y], [y], [y]
+            out [y]
+            hlt
+            p: data 0
+            x: data 0
+            y: data 0
+        ").unwrap();
+        let mut machine = SymbolicMachine::new(&program);
+        machine.bind(1, "p");
+        machine.run().unwrap();
+
+        assert_eq!(None, machine.load(14));
+        assert_eq!("in1^2", machine.output[0].as_ref().unwrap().render(&machine.names));
+    }
+
+    #[test]
+    fn test_far_write() {
+        let mut machine = SymbolicMachine::new(&[1101, 1, 1, 1_000_000_000_000, 99]);
+        machine.run().unwrap();
+        assert_eq!(Some(Expr::constant(2)), machine.load(1_000_000_000_000));
+        assert_eq!(Some(Expr::constant(0)), machine.load(1_000_000_000_001));
+    }
+
+    #[test]
+    fn test_symbolic_branch() {
+        let program = assemble("in [6]\njnz [6], #0\nhlt\ndata 0\n").unwrap();
+        let mut machine = SymbolicMachine::new(&program);
+        let err = machine.run().unwrap_err();
+        assert_eq!(SymbolicError { pointer: 2, reason: "branch on a symbolic value" }, err);
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use super::cell::Cell;
+use super::disasm::Operand;
+use super::{load_code, Code, Instruction, IntCode, Mode, Opcode, State};
+use crate::PuzzleError;
+
+// Hooks called by `IntCode::step_with`. `instruction` runs before each
+// executed instruction, `write` for each memory write it then performs.
+pub trait Tracer<C: Cell = Code> {
+    fn instruction(&mut self, _vm: &IntCode<C>, _instruction: &Instruction) {}
+    fn write(&mut self, _address: usize, _value: &C) {}
+}
+
+pub struct NoTrace;
+
+impl<C: Cell> Tracer<C> for NoTrace {}
+
+impl<C: Cell, A: Tracer<C>, B: Tracer<C>> Tracer<C> for (A, B) {
+    fn instruction(&mut self, vm: &IntCode<C>, instruction: &Instruction) {
+        self.0.instruction(vm, instruction);
+        self.1.instruction(vm, instruction);
+    }
+
+    fn write(&mut self, address: usize, value: &C) {
+        self.0.write(address, value);
+        self.1.write(address, value);
+    }
+}
+
+// Writes one line per executed instruction, with the values read by each
+// operand, followed by one line per memory write.
+pub struct TraceLog<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceLog<W> {
+    pub fn new(out: W) -> TraceLog<W> {
+        TraceLog { out, error: None }
+    }
+
+    fn log(&mut self, line: fmt::Arguments) {
+        if self.error.is_none() {
+            if let Err(err) = self.out.write_fmt(line) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.out.flush().map(|_| self.out),
+        }
+    }
+}
+
+impl<W: Write> Tracer for TraceLog<W> {
+    fn instruction(&mut self, vm: &IntCode, instruction: &Instruction) {
+        let mut line = format!("{:>5}  {}", vm.pointer, instruction.opcode.mnemonic());
+
+        for index in 0..instruction.opcode.arity() {
+            let mode = instruction.modes[index];
+            let operand = Operand { mode, value: vm.memory[vm.pointer + index + 1] };
+            line += if index == 0 { " " } else { ", " };
+            line += &operand.to_string();
+
+            let reads = mode != Mode::Immediate && instruction.opcode.write_param() != Some(index);
+            if let (true, Ok(value)) = (reads, vm.param(instruction, index)) {
+                line += &format!("={}", value);
+            }
+        }
+        self.log(format_args!("{}\n", line));
+    }
+
+    fn write(&mut self, address: usize, value: &Code) {
+        self.log(format_args!("       [{}] <- {}\n", address, value));
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Profile {
+    pub total: usize,
+    pub by_opcode: HashMap<Opcode, usize>,
+    pub by_address: HashMap<usize, usize>,
+    pub self_modifying_writes: usize,
+    executed: HashSet<usize>,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    // The `count` most executed instruction addresses, with their counts
+    pub fn hottest(&self, count: usize) -> Vec<(usize, usize)> {
+        let mut hot: Vec<(usize, usize)> = self.by_address.iter().map(|(&a, &n)| (a, n)).collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot.truncate(count);
+        hot
+    }
+}
+
+impl Tracer for Profile {
+    fn instruction(&mut self, vm: &IntCode, instruction: &Instruction) {
+        self.total += 1;
+        *self.by_opcode.entry(instruction.opcode).or_insert(0) += 1;
+        *self.by_address.entry(vm.pointer).or_insert(0) += 1;
+        self.executed.extend(vm.pointer..vm.pointer + instruction.size());
+    }
+
+    fn write(&mut self, address: usize, _value: &Code) {
+        if self.executed.contains(&address) {
+            self.self_modifying_writes += 1;
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "instructions executed: {}", self.total)?;
+        writeln!(f, "by opcode:")?;
+        for opcode in Opcode::ALL.iter() {
+            if let Some(count) = self.by_opcode.get(opcode) {
+                writeln!(f, "  {:<4} {}", opcode.mnemonic(), count)?;
+            }
+        }
+        writeln!(f, "hottest addresses:")?;
+        for (address, count) in self.hottest(10) {
+            writeln!(f, "  {:>5}  {}", address, count)?;
+        }
+        write!(f, "self-modifying writes: {}", self.self_modifying_writes)
+    }
+}
+
+pub fn command(args: &[String]) -> Result<(), PuzzleError> {
+    if args.len() < 2 {
+        return Err(PuzzleError::new("usage: intcode trace <file> <trace file> [input]..."));
+    }
+
+    let mut computer = IntCode::new(&load_code(&args[0])?);
+    for value in &args[2..] {
+        computer.push_input(value.parse()?);
+    }
+
+    let log = TraceLog::new(BufWriter::new(File::create(&args[1])?));
+    let mut tracer = (log, Profile::new());
+    let state = computer.run_with(&mut tracer)?;
+    let (log, profile) = tracer;
+    log.finish()?;
+
+    if state == State::WaitingForInput {
+        println!("Stopped waiting for input at {}", computer.pointer);
+    }
+    println!("{}", profile);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+
+    #[test]
+    fn test_trace_log() {
+        let code = [1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        let mut computer = IntCode::new(&code);
+        let mut log = TraceLog::new(Vec::new());
+        computer.run_with(&mut log).unwrap();
+
+        let expected = vec![
+            "    0  add [9]=30, [10]=40, [3]",
+            "       [3] <- 70",
+            "    4  mul [3]=70, [11]=50, [0]",
+            "       [0] <- 3500",
+            "    8  hlt",
+        ];
+        let output = String::from_utf8(log.finish().unwrap()).unwrap();
+        assert_eq!(expected, output.lines().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_profile() {
+        // Counts down from 3, then rewrites its first instruction in place
+        let code = assemble("
+            loop:   add [n], #-1, [n]
+                    jnz [n], #loop
+                    mul [loop], #1, [loop]
+                    hlt
+            n:      data 3
+        ").unwrap();
+        let mut computer = IntCode::new(&code);
+        let mut profile = Profile::new();
+        assert_eq!(Ok(State::Halted), computer.run_with(&mut profile));
+
+        assert_eq!(8, profile.total);
+        assert_eq!(Some(&3), profile.by_opcode.get(&Opcode::JumpIfTrue));
+        assert_eq!(Some(&1), profile.by_opcode.get(&Opcode::Halt));
+        assert_eq!(vec![(0, 3), (4, 3)], profile.hottest(2));
+        assert_eq!(1, profile.self_modifying_writes);
+    }
+}
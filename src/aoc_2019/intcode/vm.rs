@@ -0,0 +1,331 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+use super::cell::Cell;
+use super::memory::Memory;
+use super::trace::{NoTrace, Tracer};
+use super::{Code, Instruction, Mode, Opcode};
+use crate::PuzzleError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Running,
+    WaitingForInput,
+    Halted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VmError {
+    InvalidInstruction { pointer: usize, code: Code },
+    InvalidAddress { pointer: usize, address: Code },
+    Overflow { pointer: usize },
+    BudgetExceeded { pointer: usize, budget: u64 },
+    InfiniteLoop { start: usize, end: usize },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::InvalidInstruction { pointer, code } => {
+                write!(f, "invalid instruction {} at address {}", code, pointer)
+            }
+            VmError::InvalidAddress { pointer, address } => {
+                write!(f, "invalid address {} accessed at address {}", address, pointer)
+            }
+            VmError::Overflow { pointer } => {
+                write!(f, "arithmetic overflow at address {}", pointer)
+            }
+            VmError::BudgetExceeded { pointer, budget } => {
+                write!(f, "budget of {} instructions exceeded at address {}", budget, pointer)
+            }
+            VmError::InfiniteLoop { start, end } => {
+                write!(f, "infinite loop between addresses {} and {}", start, end)
+            }
+        }
+    }
+}
+
+impl Error for VmError {}
+
+impl From<VmError> for PuzzleError {
+    fn from(err: VmError) -> Self {
+        PuzzleError::new(&err.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decoded<C = Code> {
+    pub instruction: Instruction,
+    pub params: [C; 3],
+}
+
+#[derive(Debug, Clone)]
+pub struct IntCode<C = Code> {
+    pub memory: Memory<C>,
+    pub pointer: usize,
+    pub relative_base: C,
+    pub input: VecDeque<C>,
+    pub output: VecDeque<C>,
+}
+
+impl<C: Cell> IntCode<C> {
+    pub fn new(code: &[C]) -> IntCode<C> {
+        IntCode {
+            memory: Memory::new(code),
+            pointer: 0,
+            relative_base: C::default(),
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+        }
+    }
+
+    pub fn push_input(&mut self, value: C) {
+        self.input.push_back(value);
+    }
+
+    pub fn pop_output(&mut self) -> Option<C> {
+        self.output.pop_front()
+    }
+
+    fn check_address(&self, address: &C) -> Result<usize, VmError> {
+        match address.to_i64() {
+            Some(value) if value >= 0 => Ok(value as usize),
+            _ => Err(VmError::InvalidAddress { pointer: self.pointer, address: address.to_code_lossy() }),
+        }
+    }
+
+    fn checked(&self, value: Option<C>) -> Result<C, VmError> {
+        value.ok_or(VmError::Overflow { pointer: self.pointer })
+    }
+
+    // Instruction at the current pointer, without executing it
+    pub fn decode(&self) -> Result<Instruction, VmError> {
+        let code = &self.memory[self.pointer];
+        code.to_i64().and_then(Instruction::decode)
+            .ok_or(VmError::InvalidInstruction { pointer: self.pointer, code: code.to_code_lossy() })
+    }
+
+    fn address(&self, mode: Mode, raw: &C, index: usize) -> Result<usize, VmError> {
+        match mode {
+            Mode::Position => self.check_address(raw),
+            Mode::Relative => self.check_address(&self.checked(self.relative_base.checked_add(raw))?),
+            Mode::Immediate => Ok(self.pointer + index + 1),
+        }
+    }
+
+    fn operand(&self, mode: Mode, raw: &C, index: usize) -> Result<C, VmError> {
+        match mode {
+            Mode::Immediate => Ok(raw.clone()),
+            _ => Ok(self.memory[self.address(mode, raw, index)?].clone()),
+        }
+    }
+
+    pub fn param(&self, instruction: &Instruction, index: usize) -> Result<C, VmError> {
+        let raw = &self.memory[self.pointer + index + 1];
+        self.operand(instruction.modes[index], raw, index)
+    }
+
+    // Instruction at the current pointer along with its raw parameters
+    pub fn fetch(&self) -> Result<Decoded<C>, VmError> {
+        let instruction = self.decode()?;
+        let mut params: [C; 3] = Default::default();
+        for (index, param) in params.iter_mut().enumerate().take(instruction.opcode.arity()) {
+            *param = self.memory[self.pointer + index + 1].clone();
+        }
+        Ok(Decoded { instruction, params })
+    }
+
+    // Address the instruction will write to, if any. An input instruction
+    // waiting for a value doesn't write anything (yet).
+    pub fn write_address(&self, decoded: &Decoded<C>) -> Result<Option<usize>, VmError> {
+        if decoded.instruction.opcode == Opcode::Input && self.input.is_empty() {
+            return Ok(None);
+        }
+        match decoded.instruction.opcode.write_param() {
+            Some(index) => {
+                let mode = decoded.instruction.modes[index];
+                self.address(mode, &decoded.params[index], index).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn step(&mut self) -> Result<State, VmError> {
+        self.step_with(&mut NoTrace)
+    }
+
+    // Same as `step`, reporting the execution to the tracer. Since the tracer
+    // is a type parameter, `NoTrace` compiles down to the plain interpreter.
+    pub fn step_with<T: Tracer<C>>(&mut self, tracer: &mut T) -> Result<State, VmError> {
+        let decoded = self.fetch()?;
+        self.execute(tracer, &decoded)
+    }
+
+    // Executes an instruction previously fetched at the current pointer
+    pub fn execute<T: Tracer<C>>(&mut self, tracer: &mut T, decoded: &Decoded<C>) -> Result<State, VmError> {
+        let Decoded { instruction: instr, params } = decoded;
+        let instr = *instr;
+        let mut next = self.pointer + instr.size();
+
+        if instr.opcode == Opcode::Input && self.input.is_empty() {
+            return Ok(State::WaitingForInput);
+        }
+        tracer.instruction(self, &instr);
+
+        let arg = |vm: &IntCode<C>, index: usize| vm.operand(instr.modes[index], &params[index], index);
+        let mut write = |vm: &mut IntCode<C>, index: usize, value: C| -> Result<(), VmError> {
+            let address = vm.address(instr.modes[index], &params[index], index)?;
+            tracer.write(address, &value);
+            vm.memory[address] = value;
+            Ok(())
+        };
+
+        match instr.opcode {
+            Opcode::Add => {
+                let value = self.checked(arg(self, 0)?.checked_add(&arg(self, 1)?))?;
+                write(self, 2, value)?;
+            }
+            Opcode::Mul => {
+                let value = self.checked(arg(self, 0)?.checked_mul(&arg(self, 1)?))?;
+                write(self, 2, value)?;
+            }
+            Opcode::Input => {
+                let value = self.input[0].clone();
+                write(self, 0, value)?;
+                self.input.pop_front();
+            }
+            Opcode::Output => {
+                let value = arg(self, 0)?;
+                self.output.push_back(value);
+            }
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                let cond = !arg(self, 0)?.is_zero();
+                if cond == (instr.opcode == Opcode::JumpIfTrue) {
+                    next = self.check_address(&arg(self, 1)?)?;
+                }
+            }
+            Opcode::LessThan => {
+                let value = arg(self, 0)? < arg(self, 1)?;
+                write(self, 2, C::from_bool(value))?;
+            }
+            Opcode::Equals => {
+                let value = arg(self, 0)? == arg(self, 1)?;
+                write(self, 2, C::from_bool(value))?;
+            }
+            Opcode::AdjustBase => {
+                let base = self.relative_base.checked_add(&arg(self, 0)?);
+                self.relative_base = self.checked(base)?;
+            }
+            Opcode::Halt => return Ok(State::Halted),
+        }
+
+        self.pointer = next;
+        Ok(State::Running)
+    }
+
+    // Runs until the program halts or needs more input
+    pub fn run(&mut self) -> Result<State, VmError> {
+        self.run_with(&mut NoTrace)
+    }
+
+    pub fn run_with<T: Tracer<C>>(&mut self, tracer: &mut T) -> Result<State, VmError> {
+        loop {
+            match self.step_with(tracer)? {
+                State::Running => continue,
+                state => return Ok(state),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::asm::assemble;
+
+    fn run_with_input(code: &[Code], input: &[Code]) -> Vec<Code> {
+        let mut computer = IntCode::new(code);
+        computer.input.extend(input);
+        assert_eq!(Ok(State::Halted), computer.run());
+        computer.output.into_iter().collect()
+    }
+
+    #[test]
+    fn test_day_02_examples() {
+        let mut computer = IntCode::new(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        assert_eq!(Ok(State::Halted), computer.run());
+        assert_eq!(3500, computer.memory[0]);
+        assert_eq!(8, computer.pointer);
+    }
+
+    #[test]
+    fn test_comparisons_and_jumps() {
+        // Day 05 example: outputs 999, 1000 or 1001 if the input is below, equal or above 8
+        let code = [
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36,
+            98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000,
+            1, 20, 4, 20, 1105, 1, 46, 98, 99,
+        ];
+        assert_eq!(vec![999], run_with_input(&code, &[7]));
+        assert_eq!(vec![1000], run_with_input(&code, &[8]));
+        assert_eq!(vec![1001], run_with_input(&code, &[9]));
+    }
+
+    #[test]
+    fn test_relative_base() {
+        let code = assemble("
+            arb #7
+            in rb+2
+            out rb+2
+            hlt
+            data 0, 0, 0, 0, 0
+        ").unwrap();
+        assert_eq!(vec![42], run_with_input(&code, &[42]));
+    }
+
+    #[test]
+    fn test_waits_for_input() {
+        let mut computer = IntCode::new(&[3, 5, 4, 5, 99, 0]);
+        assert_eq!(Ok(State::WaitingForInput), computer.run());
+        assert_eq!(0, computer.pointer);
+
+        computer.push_input(7);
+        assert_eq!(Ok(State::Halted), computer.run());
+        assert_eq!(Some(7), computer.pop_output());
+    }
+
+    #[test]
+    fn test_memory_grows() {
+        let code = assemble("
+            add #1, #2, [100]
+            in [1000000000000]
+            out [100]
+            out [1000000000000]
+            out [5000]
+            hlt
+        ").unwrap();
+        assert_eq!(vec![3, 7, 0], run_with_input(&code, &[7]));
+    }
+
+    #[test]
+    fn test_large_values() {
+        let code = [1102, 3_000_000_000, 4, 7, 4, 7, 99, 0];
+        assert_eq!(vec![12_000_000_000], run_with_input(&code, &[]));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut computer = IntCode::new(&[1, 0, 0, 3, 42]);
+        assert_eq!(Ok(State::Running), computer.step());
+        let expected = VmError::InvalidInstruction { pointer: 4, code: 42 };
+        assert_eq!(Err(expected), computer.step());
+
+        let mut computer = IntCode::new(&[1, 0, -20, 3, 99]);
+        let expected = VmError::InvalidAddress { pointer: 0, address: -20 };
+        assert_eq!(Err(expected), computer.run());
+
+        let mut computer = IntCode::new(&[1102, Code::MAX, 2, 0, 99]);
+        assert_eq!(Err(VmError::Overflow { pointer: 0 }), computer.run());
+    }
+}
@@ -1,14 +1,22 @@
+// Older days predate the current lints
+#[allow(clippy::ptr_arg)]
 mod day_01;
 mod day_02;
 mod day_03;
+#[allow(unknown_lints, clippy::ptr_arg, clippy::manual_checked_ops, clippy::manual_is_multiple_of, clippy::len_zero,
+         clippy::bool_assert_comparison)]
 mod day_04;
+#[allow(clippy::needless_borrow, clippy::ptr_arg)]
 mod day_22;
 
-pub fn dispatch(day: u8, puzzle_input: &String) {
+pub mod intcode;
+
+#[allow(clippy::ptr_arg, clippy::needless_borrow)]
+pub fn dispatch(day: u8, puzzle_input: &String, options: &[String]) {
     match day {
          1 => day_01::main(&puzzle_input),
          2 => day_02::main(&puzzle_input),
-         3 => day_03::main(&puzzle_input),
+         3 => day_03::main(&puzzle_input, options),
          4 => day_04::main(&puzzle_input),
         22 => day_22::main(&puzzle_input),
          _ => println!("Day {} not found", day),
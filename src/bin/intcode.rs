@@ -0,0 +1,12 @@
+use std::env;
+use std::process;
+
+use advent_of_code::aoc_2019::intcode::cli;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    cli::command(&args[1..]).unwrap_or_else(|err| {
+        eprintln!("intcode: {}", err);
+        process::exit(1);
+    });
+}
@@ -0,0 +1,341 @@
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+// Signed integer usable as a grid coordinate
+pub trait Coordinate:
+    Copy + Ord + Default + fmt::Debug + fmt::Display
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Neg<Output = Self>
+{
+    const ONE: Self;
+
+    fn abs(self) -> Self;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+    fn checked_abs(self) -> Option<Self>;
+}
+
+macro_rules! coordinate {
+    ($($type:ty),*) => {
+        $(
+            impl Coordinate for $type {
+                const ONE: Self = 1;
+
+                fn abs(self) -> Self {
+                    <$type>::abs(self)
+                }
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$type>::checked_add(self, other)
+                }
+
+                fn checked_sub(self, other: Self) -> Option<Self> {
+                    <$type>::checked_sub(self, other)
+                }
+
+                fn checked_mul(self, other: Self) -> Option<Self> {
+                    <$type>::checked_mul(self, other)
+                }
+
+                fn checked_abs(self) -> Option<Self> {
+                    <$type>::checked_abs(self)
+                }
+            }
+        )*
+    };
+}
+
+coordinate!(i8, i16, i32, i64, i128, isize);
+
+// Point on a grid where `y` grows upwards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Point<T = i32> {
+    pub x: T,
+    pub y: T,
+}
+
+impl<T: Coordinate> Point<T> {
+    pub fn new(x: T, y: T) -> Point<T> {
+        Point { x, y }
+    }
+
+    pub fn origin() -> Point<T> {
+        Point::default()
+    }
+
+    // Takes a four- or eight-way direction
+    pub fn step(self, direction: impl Into<Direction8>) -> Point<T> {
+        self + direction.into().offset()
+    }
+
+    pub fn checked_add(self, other: Point<T>) -> Option<Point<T>> {
+        Some(Point::new(self.x.checked_add(other.x)?, self.y.checked_add(other.y)?))
+    }
+
+    // Moves `count` times in the direction, unless it leaves the range of `T`
+    pub fn checked_step(self, direction: impl Into<Direction8>, count: T) -> Option<Point<T>> {
+        let offset = direction.into().offset::<T>();
+        let offset = Point::new(offset.x.checked_mul(count)?, offset.y.checked_mul(count)?);
+        self.checked_add(offset)
+    }
+
+    pub fn manhattan(self, other: Point<T>) -> T {
+        (self.x - other.x).abs() + (self.y - other.y).abs()
+    }
+
+    pub fn checked_manhattan(self, other: Point<T>) -> Option<T> {
+        let dx = self.x.checked_sub(other.x)?.checked_abs()?;
+        let dy = self.y.checked_sub(other.y)?.checked_abs()?;
+        dx.checked_add(dy)
+    }
+
+    pub fn chebyshev(self, other: Point<T>) -> T {
+        (self.x - other.x).abs().max((self.y - other.y).abs())
+    }
+
+    // Same point with wider coordinates
+    pub fn cast<U: Coordinate + From<T>>(self) -> Point<U> {
+        Point::new(U::from(self.x), U::from(self.y))
+    }
+
+    // The four points sharing an edge with this one, clockwise from up
+    pub fn neighbours(self) -> impl Iterator<Item = Point<T>> {
+        Direction::ALL.iter().map(move |&d| self.step(d))
+    }
+
+    // The eight points sharing an edge or a corner, clockwise from up
+    pub fn neighbours8(self) -> impl Iterator<Item = Point<T>> {
+        Direction8::ALL.iter().map(move |&d| self.step(d))
+    }
+}
+
+impl<T> From<(T, T)> for Point<T> {
+    fn from((x, y): (T, T)) -> Point<T> {
+        Point { x, y }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Point<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+impl<T: Coordinate> Add for Point<T> {
+    type Output = Point<T>;
+
+    fn add(self, other: Point<T>) -> Point<T> {
+        Point::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl<T: Coordinate> Sub for Point<T> {
+    type Output = Point<T>;
+
+    fn sub(self, other: Point<T>) -> Point<T> {
+        Point::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl<T: Coordinate> Neg for Point<T> {
+    type Output = Point<T>;
+
+    fn neg(self) -> Point<T> {
+        Point::new(-self.x, -self.y)
+    }
+}
+
+impl<T: Coordinate> Mul<T> for Point<T> {
+    type Output = Point<T>;
+
+    fn mul(self, factor: T) -> Point<T> {
+        Point::new(self.x * factor, self.y * factor)
+    }
+}
+
+impl<T: Coordinate> AddAssign for Point<T> {
+    fn add_assign(&mut self, other: Point<T>) {
+        *self = *self + other;
+    }
+}
+
+impl<T: Coordinate> SubAssign for Point<T> {
+    fn sub_assign(&mut self, other: Point<T>) {
+        *self = *self - other;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction { Up, Right, Down, Left }
+
+impl Direction {
+    // Clockwise order
+    pub const ALL: [Direction; 4] = [Direction::Up, Direction::Right, Direction::Down, Direction::Left];
+
+    fn from_index(index: usize) -> Direction {
+        Direction::ALL[index % 4]
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn turn_right(self) -> Direction {
+        Direction::from_index(self.index() + 1)
+    }
+
+    pub fn turn_left(self) -> Direction {
+        Direction::from_index(self.index() + 3)
+    }
+
+    pub fn reverse(self) -> Direction {
+        Direction::from_index(self.index() + 2)
+    }
+
+    pub fn offset<T: Coordinate>(self) -> Point<T> {
+        let (zero, one) = (T::default(), T::ONE);
+        match self {
+            Direction::Up => Point::new(zero, one),
+            Direction::Right => Point::new(one, zero),
+            Direction::Down => Point::new(zero, -one),
+            Direction::Left => Point::new(-one, zero),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction8 { Up, UpRight, Right, DownRight, Down, DownLeft, Left, UpLeft }
+
+impl Direction8 {
+    // Clockwise order
+    pub const ALL: [Direction8; 8] = [
+        Direction8::Up,
+        Direction8::UpRight,
+        Direction8::Right,
+        Direction8::DownRight,
+        Direction8::Down,
+        Direction8::DownLeft,
+        Direction8::Left,
+        Direction8::UpLeft,
+    ];
+
+    fn from_index(index: usize) -> Direction8 {
+        Direction8::ALL[index % 8]
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    // Turns by 45 degrees
+    pub fn turn_right(self) -> Direction8 {
+        Direction8::from_index(self.index() + 1)
+    }
+
+    pub fn turn_left(self) -> Direction8 {
+        Direction8::from_index(self.index() + 7)
+    }
+
+    pub fn reverse(self) -> Direction8 {
+        Direction8::from_index(self.index() + 4)
+    }
+
+    pub fn is_diagonal(self) -> bool {
+        self.index() % 2 == 1
+    }
+
+    pub fn offset<T: Coordinate>(self) -> Point<T> {
+        let (zero, one) = (T::default(), T::ONE);
+        let (x, y) = match self {
+            Direction8::Up => (zero, one),
+            Direction8::UpRight => (one, one),
+            Direction8::Right => (one, zero),
+            Direction8::DownRight => (one, -one),
+            Direction8::Down => (zero, -one),
+            Direction8::DownLeft => (-one, -one),
+            Direction8::Left => (-one, zero),
+            Direction8::UpLeft => (-one, one),
+        };
+        Point::new(x, y)
+    }
+}
+
+impl From<Direction> for Direction8 {
+    fn from(direction: Direction) -> Direction8 {
+        Direction8::from_index(direction.index() * 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_ops() {
+        let a = Point::new(3, -2);
+        let b = Point::new(-1, 5);
+        assert_eq!(Point::new(2, 3), a + b);
+        assert_eq!(Point::new(4, -7), a - b);
+        assert_eq!(Point::new(-3, 2), -a);
+        assert_eq!(Point::new(9, -6), a * 3);
+
+        let mut c = a;
+        c += b;
+        c -= a;
+        assert_eq!(b, c);
+        assert_eq!("(3, -2)", a.to_string());
+    }
+
+    #[test]
+    fn test_distances() {
+        let a: Point<i16> = Point::new(3, -2);
+        assert_eq!(5, a.manhattan(Point::origin()));
+        assert_eq!(3, a.chebyshev(Point::origin()));
+        assert_eq!(11, a.manhattan(Point::new(-1, 5)));
+        assert_eq!(7, a.chebyshev(Point::new(-1, 5)));
+    }
+
+    #[test]
+    fn test_checked() {
+        let a: Point<i16> = Point::new(i16::MAX - 1, i16::MIN);
+        assert_eq!(Some(Point::new(i16::MAX, i16::MIN)), a.checked_step(Direction::Right, 1));
+        assert_eq!(None, a.checked_step(Direction::Right, 2));
+        assert_eq!(None, a.checked_step(Direction::Down, 1));
+        assert_eq!(None, Point::new(0, 0).checked_step(Direction::Down, i16::MIN));
+        assert_eq!(None, a.checked_add(Point::new(2, 0)));
+        assert_eq!(None, a.checked_step(Direction8::UpRight, 2));
+        assert_eq!(Some(Point::new(i16::MAX, -32767)), a.checked_step(Direction8::UpRight, 1));
+
+        assert_eq!(None, a.checked_manhattan(Point::origin()));
+        assert_eq!(Some(65534), a.cast::<i32>().checked_manhattan(Point::new(-32767, -32767)));
+        assert_eq!(32766 + 32768, a.cast::<i64>().manhattan(Point::origin()));
+    }
+
+    #[test]
+    fn test_turning() {
+        let mut direction = Direction::Up;
+        for &expected in &[Direction::Right, Direction::Down, Direction::Left, Direction::Up] {
+            direction = direction.turn_right();
+            assert_eq!(expected, direction);
+        }
+        assert_eq!(Direction::Left, Direction::Up.turn_left());
+        assert_eq!(Direction::Down, Direction::Up.reverse());
+
+        assert_eq!(Direction8::UpLeft, Direction8::Up.turn_left());
+        assert_eq!(Direction8::DownRight, Direction8::UpLeft.reverse());
+        assert_eq!(Direction8::Left, Direction8::from(Direction::Left));
+        assert!(Direction8::DownLeft.is_diagonal());
+    }
+
+    #[test]
+    fn test_neighbours() {
+        let p: Point<i64> = Point::new(1, 1);
+        let four: Vec<Point<i64>> = p.neighbours().collect();
+        assert_eq!(vec![(1, 2), (2, 1), (1, 0), (0, 1)], four.iter().map(|p| (p.x, p.y)).collect::<Vec<_>>());
+
+        let eight: Vec<Point<i64>> = p.neighbours8().collect();
+        assert_eq!(8, eight.len());
+        assert!(eight.iter().all(|&n| n.chebyshev(p) == 1));
+        assert_eq!(Point::new(2, 2), eight[1]);
+    }
+}
@@ -6,18 +6,21 @@ use std::num::ParseIntError;
 use std::time::Instant;
 
 pub mod aoc_2019;
+pub mod geometry;
 
 pub struct Puzzle {
     year: u16,
     day: u8,
-    input: String
+    input: String,
+    // Extra arguments given to the day
+    options: Vec<String>
 }
 
 impl Puzzle {
     pub fn new(year: u16, day: u8) -> Result<Puzzle, PuzzleError> {
         let filename = format!("data/{}/day_{:02}.txt", year, day);
         let input = fs::read_to_string(filename)?;
-        Ok(Puzzle { year, day, input })
+        Ok(Puzzle { year, day, input, options: Vec::new() })
     }
 
     pub fn from_args(args: &[String]) -> Result<Puzzle, PuzzleError> {
@@ -28,14 +31,16 @@ impl Puzzle {
         let year = args[1].parse()?;
         let day = args[2].parse()?;
 
-        Puzzle::new(year, day)
+        let mut puzzle = Puzzle::new(year, day)?;
+        puzzle.options = args[3..].to_vec();
+        Ok(puzzle)
     }
 
     pub fn dispatch(&self) {
         println!("=== Advent of Code {}, day {}", self.year, self.day);
         let now = Instant::now();
         match self.year {
-            2019 => aoc_2019::dispatch(self.day, &self.input),
+            2019 => aoc_2019::dispatch(self.day, &self.input, &self.options),
             _ => println!("Year {} not available", self.year),
         };
         println!("    Ran in {} µs", now.elapsed().as_micros());
@@ -69,12 +74,12 @@ impl Error for PuzzleError {
 
 impl From<ParseIntError> for PuzzleError {
     fn from(err: ParseIntError) -> Self {
-        PuzzleError::new(err.description())
+        PuzzleError::new(&err.to_string())
     }
 }
 
 impl From<io::Error> for PuzzleError {
     fn from(err: io::Error) -> Self {
-        PuzzleError::new(err.description())
+        PuzzleError::new(&err.to_string())
     }
 }
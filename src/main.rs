@@ -1,10 +1,19 @@
 use std::env;
 use std::process;
 
+use advent_of_code::aoc_2019::intcode;
 use advent_of_code::Puzzle;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    if args.len() > 1 && args[1] == "intcode" {
+        intcode::command(&args[2..]).unwrap_or_else(|err| {
+            println!("IntCode command failed: {}", err);
+            process::exit(1);
+        });
+        return;
+    }
+
     let puzzle = Puzzle::from_args(&args).unwrap_or_else(|err| {
         println!("Couldn't load puzzle: {:?}", err);
         process::exit(1);